@@ -1,13 +1,41 @@
 use anyhow::{Context, Result};
+use std::path::Path;
 use std::process::Command;
 
 use crate::config;
 use crate::hooks::{self, HookContext};
 use crate::lima::{client as lima, template};
+use crate::preflight;
 use crate::state::{Instance, State};
 use crate::utils;
 
-pub fn execute(name: &str, base_branch: Option<Option<String>>, worktree_only: bool) -> Result<()> {
+/// Lima テンプレートを生成して VM を作成
+fn create_vm(
+    config: &config::Config,
+    main_repo: &Path,
+    worktree_path: &Path,
+    name: &str,
+    lima_instance: &str,
+    force: bool,
+) -> Result<()> {
+    println!("Creating Lima VM template...");
+    let mut template_config =
+        template::TemplateConfig::from_config(config, main_repo, worktree_path)?;
+    template_config.set_instance(name, name);
+    let temp_template = template::create_temp_template(&template_config)?;
+
+    println!("Creating Lima VM: {}...", lima_instance);
+    let resources = preflight::resources_for_new(&template_config, false)?;
+    preflight::check(lima_instance, resources, force)?;
+    lima::create(temp_template.path(), lima_instance)
+}
+
+pub fn execute(
+    name: &str,
+    base_branch: Option<Option<String>>,
+    worktree_only: bool,
+    force: bool,
+) -> Result<()> {
     println!("=== Adding worktree: {} ===", name);
 
     // Lima が利用可能か確認（worktree-only モードでは不要）
@@ -97,22 +125,9 @@ pub fn execute(name: &str, base_branch: Option<Option<String>>, worktree_only: b
     }
 
     if !worktree_only {
-        // Lima テンプレートを生成
-        println!("Creating Lima VM template...");
-        let mut template_config = template::TemplateConfig::new(
-            &worktree_path.to_string_lossy(),
-            config.vm_mount_type.as_deref(),
-            config.vm_user.as_deref(),
-        );
-        template_config.resolve_template(config.vm_template.as_deref(), &main_repo, &worktree_path);
-        if let Some(scripts) = &config.vm_provision_scripts {
-            template_config.load_provision_scripts(scripts, &main_repo)?;
-        }
-        let temp_template = template::create_temp_template(&template_config)?;
-
-        // Lima VM を作成
-        println!("Creating Lima VM: {}...", lima_instance);
-        if let Err(e) = lima::create(temp_template.path(), &lima_instance) {
+        // テンプレート生成の失敗も含めて、VM を作成できなければ worktree を片付ける
+        let created = create_vm(&config, &main_repo, &worktree_path, name, &lima_instance, force);
+        if let Err(e) = created {
             // 失敗した場合は worktree を削除
             eprintln!("Failed to create Lima VM, cleaning up worktree...");
             let _ = Command::new("git")
@@ -132,6 +147,8 @@ pub fn execute(name: &str, base_branch: Option<Option<String>>, worktree_only: b
         active_forwards: Vec::new(),
         active_proxy: None,
         active_browser: None,
+        last_used_at: None,
+        last_compose_at: None,
     };
 
     state.add_instance(instance);
@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::config;
+use crate::images;
+use crate::images::cache::{self, CacheEntry};
+use crate::utils;
+
+/// fracta.toml の image_cache_max_size（リポジトリ外で実行した場合は None）
+fn configured_max_size() -> Result<Option<u64>> {
+    let main_repo = match utils::resolve_main_repo() {
+        Ok(main_repo) => main_repo,
+        Err(_) => return Ok(None),
+    };
+    let config = config::load_config(&main_repo, None)?;
+    config
+        .image_cache_max_size
+        .as_deref()
+        .map(utils::parse_size)
+        .transpose()
+        .context("Invalid image_cache_max_size")
+}
+
+fn total_size(entries: &[CacheEntry]) -> u64 {
+    entries.iter().map(|e| e.size).sum()
+}
+
+/// キャッシュの一覧（最終利用が新しい順）
+pub fn list() -> Result<()> {
+    let entries = cache::entries()?;
+    if entries.is_empty() {
+        println!("Image cache is empty ({}).", cache::cache_dir()?.display());
+        return Ok(());
+    }
+
+    let now = utils::unix_now();
+    println!("{:<14} {:>10} {:>10}  IMAGES", "KEY", "SIZE", "LAST USED");
+    for entry in &entries {
+        let images = if entry.images.is_empty() {
+            "-".to_string()
+        } else {
+            entry.images.join(", ")
+        };
+        println!(
+            "{:<14} {:>10} {:>10}  {}",
+            &entry.key[..12.min(entry.key.len())],
+            utils::format_size(entry.size),
+            format!(
+                "{} ago",
+                utils::format_duration(Duration::from_secs(now.saturating_sub(entry.last_used)))
+            ),
+            images
+        );
+    }
+    println!(
+        "\n{} entries, {} total",
+        entries.len(),
+        utils::format_size(total_size(&entries))
+    );
+    Ok(())
+}
+
+/// キャッシュの合計サイズ
+pub fn size() -> Result<()> {
+    let entries = cache::entries()?;
+    let total = total_size(&entries);
+    match configured_max_size()? {
+        Some(max) => println!(
+            "{} in {} entries (limit: {})",
+            utils::format_size(total),
+            entries.len(),
+            utils::format_size(max)
+        ),
+        None => println!("{} in {} entries", utils::format_size(total), entries.len()),
+    }
+    Ok(())
+}
+
+/// 古いエントリと上限を超えた分（最終利用が古い順）を削除
+pub fn prune(max_size: Option<&str>, older_than: Option<&str>) -> Result<()> {
+    let max_size = match max_size {
+        Some(value) => Some(utils::parse_size(value).context("Invalid --max-size")?),
+        None => configured_max_size()?,
+    };
+    let max_age = match older_than {
+        Some(value) => utils::parse_duration(value).context("Invalid --older-than")?,
+        None => cache::CACHE_MAX_AGE,
+    };
+
+    let entries = cache::entries()?;
+    let evicted = cache::select_evictions(
+        &entries,
+        &HashSet::new(),
+        utils::unix_now(),
+        Some(max_age),
+        max_size,
+    );
+    if evicted.is_empty() {
+        println!("Nothing to prune.");
+    } else {
+        print_removed(&cache::remove(&evicted)?);
+    }
+
+    // registry モードのレジストリ（fracta-registry ボリューム）
+    match images::registry_gc() {
+        Ok(Some((before, after))) => println!(
+            "Registry garbage collection: {} -> {} ({} reclaimed).",
+            utils::format_size(before),
+            utils::format_size(after),
+            utils::format_size(before.saturating_sub(after))
+        ),
+        Ok(None) => {}
+        Err(e) => eprintln!("Warning: registry garbage collection failed: {}", e),
+    }
+    Ok(())
+}
+
+/// キャッシュとレジストリのデータをすべて削除
+pub fn clear() -> Result<()> {
+    print_removed(&cache::clear()?);
+    match images::remove_registry() {
+        Ok(true) => println!("Removed the local registry and its volume."),
+        Ok(false) => {}
+        Err(e) => eprintln!("Warning: failed to remove the local registry: {}", e),
+    }
+    Ok(())
+}
+
+fn print_removed(removed: &cache::Removed) {
+    println!(
+        "Removed {} entries ({}).",
+        removed.entries,
+        utils::format_size(removed.bytes)
+    );
+    if removed.in_use > 0 {
+        println!(
+            "Skipped {} entries in use by another fracta process.",
+            removed.in_use
+        );
+    }
+}
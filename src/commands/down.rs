@@ -94,6 +94,9 @@ pub fn execute(name: Option<&str>, stop_vm: bool) -> Result<()> {
         println!("Lima VM '{}' is not running.", instance.lima_instance);
     }
 
+    state.record_compose_activity(&instance_name)?;
+    state.save(&main_repo)?;
+
     hooks::run_hook("post_down", &worktree_path, &hook_ctx, &config)?;
 
     println!("=== docker compose down completed ===");
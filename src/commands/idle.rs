@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+use crate::commands::vm;
+use crate::config;
+use crate::lima::client as lima;
+use crate::state::{Instance, State};
+use crate::utils;
+
+/// インスタンスが最後に使われた時刻
+/// fracta 操作・compose 操作・VM 起動のうち最も新しいもの
+pub fn last_active_at(instance: &Instance) -> Option<u64> {
+    instance
+        .last_activity()
+        .max(lima::started_at(&instance.lima_instance))
+}
+
+/// アイドル時間がしきい値を超えているか
+fn is_idle(last_active: Option<u64>, now: u64, threshold: Duration) -> bool {
+    match last_active {
+        Some(at) => now.saturating_sub(at) > threshold.as_secs(),
+        None => false,
+    }
+}
+
+/// しきい値を超えてアイドル状態の VM を停止する（daemon / cron から呼ぶ想定）
+pub fn execute(after: Option<&str>, dry_run: bool) -> Result<()> {
+    let main_repo = utils::resolve_main_repo()?;
+    let config = config::load_config(&main_repo, None)?;
+
+    let threshold = match after.or(config.idle_stop_after.as_deref()) {
+        Some(value) => utils::parse_duration(value)
+            .context(format!("Invalid idle_stop_after value '{}'", value))?,
+        None => anyhow::bail!(
+            "Idle threshold is not set. Use --after or set idle_stop_after in fracta.toml."
+        ),
+    };
+
+    let mut state = State::load(&main_repo)?;
+    let now = utils::unix_now();
+    let mut stopped = 0;
+
+    let candidates: Vec<Instance> = state
+        .instances
+        .iter()
+        .filter(|inst| !inst.lima_instance.is_empty())
+        .cloned()
+        .collect();
+
+    for instance in candidates {
+        if lima::info(&instance.lima_instance)? != lima::InstanceStatus::Running {
+            continue;
+        }
+
+        let last_active = last_active_at(&instance);
+        let idle_for = last_active
+            .map(|at| utils::format_duration(Duration::from_secs(now.saturating_sub(at))))
+            .unwrap_or_else(|| "unknown".to_string());
+
+        if !is_idle(last_active, now, threshold) {
+            println!("Active: {} (idle {})", instance.name, idle_for);
+            continue;
+        }
+
+        if dry_run {
+            println!("Would stop: {} (idle {})", instance.name, idle_for);
+            continue;
+        }
+
+        println!("Stopping idle VM: {} (idle {})", instance.name, idle_for);
+        if let Err(e) = vm::stop_instance(&mut state, &main_repo, &instance) {
+            eprintln!("Warning: Failed to stop {}: {}", instance.name, e);
+            continue;
+        }
+        stopped += 1;
+    }
+
+    if !dry_run {
+        println!(
+            "Idle stop completed: {} VM(s) stopped (threshold {}).",
+            stopped,
+            utils::format_duration(threshold)
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_idle() {
+        let threshold = Duration::from_secs(3600);
+        assert!(is_idle(Some(1000), 1000 + 3601, threshold));
+        assert!(!is_idle(Some(1000), 1000 + 3600, threshold));
+        assert!(!is_idle(None, 10_000, threshold));
+    }
+}
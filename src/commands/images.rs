@@ -0,0 +1,60 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::config;
+use crate::images;
+use crate::lima::client as lima;
+use crate::state::{Instance, State};
+use crate::utils;
+
+fn ensure_running(instance: &Instance) -> Result<()> {
+    if lima::info(&instance.lima_instance)? != lima::InstanceStatus::Running {
+        anyhow::bail!(
+            "Lima VM '{}' is not running. Start it with 'fracta vm start {}'.",
+            instance.lima_instance,
+            instance.name
+        );
+    }
+    Ok(())
+}
+
+/// インスタンス VM 間でイメージをコピー（省略時は from の compose が参照するイメージ）
+pub fn copy(from: &str, to: &str, images: &[String]) -> Result<()> {
+    let main_repo = utils::resolve_main_repo()?;
+    let mut state = State::load(&main_repo)?;
+    let source = state.resolve_instance(Some(from))?.clone();
+    let target = state.resolve_instance(Some(to))?.clone();
+    if source.lima_instance == target.lima_instance {
+        anyhow::bail!("Source and destination are the same instance: {}", source.name);
+    }
+    ensure_running(&source)?;
+    ensure_running(&target)?;
+
+    let worktree_path = Path::new(&source.path);
+    let config = config::load_config(&main_repo, Some(worktree_path))?;
+    let images = if images.is_empty() {
+        let compose_base = utils::compose_base_path(&config, worktree_path);
+        let mut names: Vec<String> = images::collect_compose_images(&compose_base, worktree_path)?
+            .into_iter()
+            .map(|image| image.image)
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    } else {
+        images.to_vec()
+    };
+    if images.is_empty() {
+        println!("No images found to copy.");
+        return Ok(());
+    }
+
+    let jobs = config.image_sync_jobs.unwrap_or(images::DEFAULT_SYNC_JOBS);
+    println!("Copying images from {} to {}...", source.name, target.name);
+    images::copy_between_vms(&source.lima_instance, &target.lima_instance, &images, jobs)?;
+
+    state.touch_instance(&source.name)?;
+    state.touch_instance(&target.name)?;
+    state.save(&main_repo)?;
+    Ok(())
+}
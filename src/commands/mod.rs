@@ -1,15 +1,22 @@
 pub mod add;
 pub mod browser;
+pub mod cache;
 pub mod close;
 pub mod down;
+pub mod idle;
+pub mod images;
+pub mod net;
 pub mod open;
 pub mod ports;
 pub mod ps;
+pub mod prune;
 pub mod proxy;
 pub mod proxies;
 pub mod remove;
 pub mod restart;
+pub mod stats;
 pub mod status;
 pub mod unproxy;
 pub mod up;
 pub mod vm;
+pub mod vm_image;
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::lima::{client as lima, template};
+use crate::state::State;
+use crate::utils;
+
+const HOSTS_BEGIN: &str = "# BEGIN fracta";
+const HOSTS_END: &str = "# END fracta";
+
+struct NetEntry {
+    name: String,
+    lima_instance: String,
+    status: lima::InstanceStatus,
+    hostname: String,
+    ip: Option<String>,
+}
+
+/// `ip -4 -o addr show` の出力から IPv4 アドレスを取り出す
+fn parse_ip_addr(output: &str) -> Option<String> {
+    let mut tokens = output.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if token == "inet" {
+            let cidr = tokens.next()?;
+            return cidr.split('/').next().map(|ip| ip.to_string());
+        }
+    }
+    None
+}
+
+/// VM 作成時のテンプレートから共有ネットワークのインターフェース名を求める
+fn vm_interface(lima_instance: &str) -> Option<String> {
+    let created = lima::instance_dir(lima_instance).join("lima.yaml");
+    let content = std::fs::read_to_string(created).ok()?;
+    template::shared_interface(&content)
+}
+
+fn vm_ip(lima_instance: &str) -> Result<Option<String>> {
+    let Some(interface) = vm_interface(lima_instance) else {
+        return Ok(None);
+    };
+    let output = lima::shell(
+        lima_instance,
+        &[
+            "bash",
+            "-c",
+            &format!("ip -4 -o addr show dev {} 2>/dev/null", interface),
+        ],
+    )?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(parse_ip_addr(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn collect_entries(state: &State) -> Vec<NetEntry> {
+    state
+        .instances
+        .iter()
+        .filter(|inst| !inst.lima_instance.is_empty())
+        .map(|inst| {
+            let status = lima::info(&inst.lima_instance).unwrap_or(lima::InstanceStatus::NotFound);
+            let ip = if status == lima::InstanceStatus::Running {
+                vm_ip(&inst.lima_instance).unwrap_or(None)
+            } else {
+                None
+            };
+            NetEntry {
+                name: inst.name.clone(),
+                lima_instance: inst.lima_instance.clone(),
+                status,
+                hostname: lima::hostname(&inst.name),
+                ip,
+            }
+        })
+        .collect()
+}
+
+/// /etc/hosts の fracta 管理ブロックを書き換えるスクリプトを生成
+fn hosts_script(entries: &[(String, String)]) -> String {
+    let mut lines = vec![format!("'{}'", HOSTS_BEGIN)];
+    for (ip, hostname) in entries {
+        lines.push(format!("'{} {}'", ip, hostname));
+    }
+    lines.push(format!("'{}'", HOSTS_END));
+
+    format!(
+        "sudo sed -i '/^{begin}$/,/^{end}$/d' /etc/hosts && printf '%s\\n' {lines} | sudo tee -a /etc/hosts > /dev/null",
+        begin = HOSTS_BEGIN,
+        end = HOSTS_END,
+        lines = lines.join(" "),
+    )
+}
+
+/// 同じホスト名になるインスタンスがあればエラー（サニタイズ後に衝突する名前）
+fn check_hostname_collisions<'a>(
+    entries: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<()> {
+    let mut seen: HashMap<&str, &str> = HashMap::new();
+    for (name, hostname) in entries {
+        if let Some(other) = seen.insert(hostname, name) {
+            anyhow::bail!(
+                "Instances '{}' and '{}' both map to hostname '{}'. Rename one of the worktrees.",
+                other,
+                name,
+                hostname
+            );
+        }
+    }
+    Ok(())
+}
+
+fn write_hosts(entries: &[NetEntry]) -> Result<usize> {
+    check_hostname_collisions(entries.iter().map(|e| (e.name.as_str(), e.hostname.as_str())))?;
+    let mapping: Vec<(String, String)> = entries
+        .iter()
+        .filter_map(|e| e.ip.as_ref().map(|ip| (ip.clone(), e.hostname.clone())))
+        .collect();
+    let script = hosts_script(&mapping);
+
+    let mut updated = 0;
+    for entry in entries.iter().filter(|e| e.ip.is_some()) {
+        let output = lima::shell(&entry.lima_instance, &["bash", "-c", &script])?;
+        if output.status.success() {
+            updated += 1;
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            eprintln!(
+                "Warning: Failed to update /etc/hosts in {}: {}",
+                entry.lima_instance,
+                stderr.trim()
+            );
+        }
+    }
+    Ok(updated)
+}
+
+/// 起動中の全 VM の /etc/hosts に各インスタンスのホスト名を書き込む
+pub fn sync_hosts(state: &State) -> Result<()> {
+    let entries = collect_entries(state);
+    let updated = write_hosts(&entries)?;
+    if updated > 0 {
+        println!("Updated instance hostnames in {} VM(s).", updated);
+    }
+    Ok(())
+}
+
+/// インスタンスの IP とホスト名を表示
+pub fn execute(sync: bool) -> Result<()> {
+    let main_repo = utils::resolve_main_repo()?;
+    let state = State::load(&main_repo)?;
+
+    let entries = collect_entries(&state);
+    if entries.is_empty() {
+        println!("No instances found.");
+        return Ok(());
+    }
+
+    println!("=== VM Network ===");
+    println!("{:<20} {:<25} {:<10} {:<30} IP", "NAME", "LIMA VM", "VM STATUS", "HOSTNAME");
+    println!("{}", "-".repeat(100));
+    for entry in &entries {
+        println!(
+            "{:<20} {:<25} {:<10} {:<30} {}",
+            entry.name,
+            entry.lima_instance,
+            entry.status.to_string(),
+            entry.hostname,
+            entry.ip.as_deref().unwrap_or("-")
+        );
+    }
+
+    if sync {
+        let updated = write_hosts(&entries)?;
+        println!("\nUpdated /etc/hosts in {} VM(s).", updated);
+    } else if entries.iter().any(|e| e.ip.is_some()) {
+        println!("\nRun 'fracta net --sync-hosts' to make these hostnames resolvable in every VM.");
+    } else {
+        println!("\nNo shared network address found. Set vm_network = \"user-v2\" in fracta.toml.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ip_addr() {
+        let output = "3: lima0    inet 192.168.104.3/24 metric 100 brd 192.168.104.255 scope global dynamic lima0\\       valid_lft 86000sec preferred_lft 86000sec\n";
+        assert_eq!(parse_ip_addr(output).as_deref(), Some("192.168.104.3"));
+        assert_eq!(parse_ip_addr(""), None);
+    }
+
+    #[test]
+    fn test_hosts_script() {
+        let script = hosts_script(&[("192.168.104.3".to_string(), "develop.fracta".to_string())]);
+        assert!(script.contains("sed -i '/^# BEGIN fracta$/,/^# END fracta$/d' /etc/hosts"));
+        assert!(script.contains("'192.168.104.3 develop.fracta'"));
+        assert!(script.ends_with("sudo tee -a /etc/hosts > /dev/null"));
+    }
+
+    #[test]
+    fn test_check_hostname_collisions() {
+        let a = lima::hostname("feature/login");
+        let b = lima::hostname("feature-login");
+        assert_eq!(a, b);
+        assert!(
+            check_hostname_collisions([("feature/login", a.as_str()), ("feature-login", b.as_str())])
+                .is_err()
+        );
+
+        let c = lima::hostname("develop");
+        assert!(check_hostname_collisions([("feature/login", a.as_str()), ("develop", c.as_str())]).is_ok());
+    }
+}
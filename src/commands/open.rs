@@ -97,6 +97,7 @@ pub fn execute(name: Option<&str>, browser: &str, url: &str, headless: bool) ->
         pid,
     };
     state.add_browser(name, session)?;
+    state.touch_instance(name)?;
     state.save(&main_repo)?;
 
     println!("Playwright started (PID {}).", pid);
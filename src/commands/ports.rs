@@ -59,7 +59,7 @@ pub fn execute(name: Option<&str>, short: bool) -> Result<()> {
         println!("Use 'fracta forward {} <local_port> <remote_port>' to create one.", instance_name);
     } else {
         println!("\nActive port forwards:");
-        println!("{:<12} {:<12} {:<10} {}", "LOCAL", "REMOTE", "PID", "ACCESS");
+        println!("{:<12} {:<12} {:<10} ACCESS", "LOCAL", "REMOTE", "PID");
         println!("{}", "-".repeat(60));
         for fwd in &active_forwards {
             println!(
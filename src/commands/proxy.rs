@@ -87,6 +87,7 @@ pub fn execute(name: Option<&str>, port: Option<u16>) -> Result<()> {
     };
 
     state.add_proxy(name, proxy)?;
+    state.touch_instance(name)?;
     state.save(&main_repo)?;
 
     println!("SOCKS5 proxy started successfully.");
@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+
+use crate::commands;
+use crate::config::{self, Config};
+use crate::lima::client as lima;
+use crate::preflight;
+use crate::state::{Instance, State};
+use crate::utils;
+
+/// docker の prune の強さ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruneLevel {
+    /// dangling イメージ・停止中コンテナ・未使用ネットワーク・ビルドキャッシュの一部
+    Dangling,
+    /// コンテナから使われていない全イメージと全ビルドキャッシュ
+    Unused,
+    /// unused に加えて未使用ボリュームも削除
+    All,
+}
+
+impl PruneLevel {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "dangling" => Ok(PruneLevel::Dangling),
+            "unused" => Ok(PruneLevel::Unused),
+            "all" => Ok(PruneLevel::All),
+            other => anyhow::bail!(
+                "Unsupported prune level '{}' (expected dangling, unused or all)",
+                other
+            ),
+        }
+    }
+
+    /// VM 内で実行するスクリプト
+    fn script(self) -> &'static str {
+        match self {
+            PruneLevel::Dangling => {
+                "sudo docker system prune -f && sudo docker builder prune -f"
+            }
+            PruneLevel::Unused => {
+                "sudo docker system prune -af && sudo docker builder prune -af"
+            }
+            PruneLevel::All => {
+                "sudo docker system prune -af --volumes && sudo docker builder prune -af"
+            }
+        }
+    }
+}
+
+/// prune 出力の "Total reclaimed space: X"（system prune）と
+/// "Total: X"（builder prune）を合計（バイト）
+fn parse_reclaimed(output: &str) -> u64 {
+    output
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            line.strip_prefix("Total reclaimed space:")
+                .or_else(|| line.strip_prefix("Total:"))
+        })
+        .filter_map(|value| utils::parse_size(value.trim()).ok())
+        .sum()
+}
+
+fn prune_vm(lima_instance: &str, level: PruneLevel) -> Result<u64> {
+    let output = lima::shell(lima_instance, &["bash", "-c", level.script()])?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("docker prune failed in {}: {}", lima_instance, stderr.trim());
+    }
+    Ok(parse_reclaimed(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// 停止中の VM を一時的に起動して prune し、再度停止する
+fn prune_stopped_vm(config: &Config, instance: &Instance, level: PruneLevel) -> Result<u64> {
+    preflight::check(
+        &instance.lima_instance,
+        preflight::resources_for_existing(&instance.lima_instance)?,
+        false,
+    )?;
+    println!("Starting Lima VM for pruning: {}...", instance.lima_instance);
+    lima::start_with_timeout(&instance.lima_instance, config.vm_start_timeout())?;
+
+    let result = prune_vm(&instance.lima_instance, level);
+
+    println!("Stopping Lima VM: {}...", instance.lima_instance);
+    lima::stop(&instance.lima_instance)?;
+    result
+}
+
+fn resolve_level(config: &Config, level: Option<&str>) -> Result<PruneLevel> {
+    match level.or(config.vm_prune_level.as_deref()) {
+        Some(value) => PruneLevel::parse(value).context("Invalid prune level"),
+        None => Ok(PruneLevel::Dangling),
+    }
+}
+
+/// インスタンス VM 内の Docker イメージ・ビルドキャッシュを削除
+pub fn execute(
+    name: Option<&str>,
+    all: bool,
+    level: Option<&str>,
+    start_stopped: bool,
+) -> Result<()> {
+    let main_repo = utils::resolve_main_repo()?;
+    let config = config::load_config(&main_repo, None)?;
+    let level = resolve_level(&config, level)?;
+    let state = State::load(&main_repo)?;
+
+    let targets: Vec<Instance> = if all {
+        state
+            .instances
+            .iter()
+            .filter(|inst| !inst.lima_instance.is_empty())
+            .cloned()
+            .collect()
+    } else {
+        vec![state.resolve_instance(name)?.clone()]
+    };
+
+    let mut results: Vec<(String, u64)> = Vec::new();
+    for instance in &targets {
+        let reclaimed = match lima::info(&instance.lima_instance)? {
+            lima::InstanceStatus::Running => {
+                println!("Pruning Docker data in {}...", instance.name);
+                prune_vm(&instance.lima_instance, level)
+            }
+            lima::InstanceStatus::Stopped if start_stopped => {
+                // prune のために他の VM は止めない
+                if commands::vm::would_exceed_capacity(&state, &config, instance)? {
+                    println!(
+                        "Skipping {}: starting it would exceed max_running_vms ({}).",
+                        instance.name,
+                        config.max_running_vms.unwrap_or_default()
+                    );
+                    continue;
+                }
+                println!("Pruning Docker data in {}...", instance.name);
+                prune_stopped_vm(&config, instance, level)
+            }
+            lima::InstanceStatus::Stopped => {
+                println!(
+                    "Skipping {}: VM is stopped (use --start-stopped to prune it).",
+                    instance.name
+                );
+                continue;
+            }
+            lima::InstanceStatus::NotFound => {
+                if !all {
+                    anyhow::bail!(
+                        "Lima VM '{}' not found. Run 'fracta add {}' first.",
+                        instance.lima_instance,
+                        instance.name
+                    );
+                }
+                continue;
+            }
+        };
+
+        match reclaimed {
+            Ok(bytes) => results.push((instance.name.clone(), bytes)),
+            Err(e) if all => eprintln!("Warning: Failed to prune {}: {}", instance.name, e),
+            Err(e) => return Err(e),
+        }
+    }
+
+    if results.is_empty() {
+        println!("No VMs were pruned.");
+        return Ok(());
+    }
+
+    println!("\n{:<30} RECLAIMED", "NAME");
+    for (name, bytes) in &results {
+        println!("{:<30} {}", name, utils::format_size(*bytes));
+    }
+    let total: u64 = results.iter().map(|(_, bytes)| bytes).sum();
+    println!("{:<30} {}", "TOTAL", utils::format_size(total));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_level_parse() {
+        assert_eq!(PruneLevel::parse("dangling").unwrap(), PruneLevel::Dangling);
+        assert_eq!(PruneLevel::parse("Unused").unwrap(), PruneLevel::Unused);
+        assert_eq!(PruneLevel::parse("all").unwrap(), PruneLevel::All);
+        assert!(PruneLevel::parse("everything").is_err());
+        assert!(PruneLevel::All.script().contains("--volumes"));
+    }
+
+    #[test]
+    fn test_parse_reclaimed() {
+        let output = "Deleted Images:\ndeleted: sha256:abc\n\nTotal reclaimed space: 1.5GB\nID\tRECLAIMABLE\tSIZE\nxyz\ttrue\t10MB\nTotal:\t500MB\nTotal reclaimed space: 0B\n";
+        assert_eq!(parse_reclaimed(output), 2_000_000_000);
+        assert_eq!(parse_reclaimed(""), 0);
+    }
+}
@@ -57,35 +57,33 @@ pub fn execute(
 
     // Lima VM が起動している場合は docker compose down を実行
     let info = lima::info(&instance.lima_instance)?;
-    if remove_vm && info == lima::InstanceStatus::Running {
-        if compose_base.exists() {
-            let compose_rel = compose_base
-                .strip_prefix(&worktree_path)
-                .unwrap_or(&compose_base);
-            let compose_path = compose_rel.to_string_lossy();
-            let vm_worktree_path = worktree_path.to_string_lossy();
-            let project_name = utils::sanitize_name(&instance.name);
-            let env_prefix = format!("COMPOSE_PROJECT_NAME={} ", project_name);
-
-            println!("Running docker compose down in VM...");
-            let output = lima::shell(
-                &instance.lima_instance,
-                &[
-                    "bash",
-                    "-c",
-                    &format!(
-                        "cd '{}' && {}sudo docker compose -f '{}' down 2>/dev/null || true",
-                        vm_worktree_path, env_prefix, compose_path
-                    ),
-                ],
-            );
-
-            if let Err(e) = output {
-                if force {
-                    eprintln!("Warning: docker compose down failed: {}", e);
-                } else {
-                    return Err(e);
-                }
+    if remove_vm && info == lima::InstanceStatus::Running && compose_base.exists() {
+        let compose_rel = compose_base
+            .strip_prefix(&worktree_path)
+            .unwrap_or(&compose_base);
+        let compose_path = compose_rel.to_string_lossy();
+        let vm_worktree_path = worktree_path.to_string_lossy();
+        let project_name = utils::sanitize_name(&instance.name);
+        let env_prefix = format!("COMPOSE_PROJECT_NAME={} ", project_name);
+
+        println!("Running docker compose down in VM...");
+        let output = lima::shell(
+            &instance.lima_instance,
+            &[
+                "bash",
+                "-c",
+                &format!(
+                    "cd '{}' && {}sudo docker compose -f '{}' down 2>/dev/null || true",
+                    vm_worktree_path, env_prefix, compose_path
+                ),
+            ],
+        );
+
+        if let Err(e) = output {
+            if force {
+                eprintln!("Warning: docker compose down failed: {}", e);
+            } else {
+                return Err(e);
             }
         }
     }
@@ -9,9 +9,9 @@ use crate::utils;
 
 pub fn execute(name: Option<&str>) -> Result<()> {
     let main_repo = utils::resolve_main_repo()?;
-    let state = State::load(&main_repo)?;
+    let mut state = State::load(&main_repo)?;
 
-    let instance = state.resolve_instance(name)?;
+    let instance = state.resolve_instance(name)?.clone();
     let name = instance.name.as_str();
 
     println!("=== Restarting worktree: {} ===", name);
@@ -72,6 +72,9 @@ pub fn execute(name: Option<&str>) -> Result<()> {
         anyhow::bail!("docker compose restart failed in VM");
     }
 
+    state.record_compose_activity(name)?;
+    state.save(&main_repo)?;
+
     hooks::run_hook("post_restart", &worktree_path, &hook_ctx, &config)?;
 
     println!("=== docker compose restart completed ===");
@@ -0,0 +1,294 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::lima::client as lima;
+use crate::state::{Instance, State};
+use crate::utils;
+
+/// 1 回の limactl shell でまとめて情報を取得するスクリプト
+const STATS_SCRIPT: &str = r#"echo '@@loadavg'; cat /proc/loadavg
+echo '@@nproc'; nproc
+echo '@@meminfo'; grep -E '^(MemTotal|MemAvailable):' /proc/meminfo
+echo '@@df'; df -B1 -P / | tail -n 1
+echo '@@docker_df'; sudo docker system df --format '{{json .}}' 2>/dev/null
+echo '@@docker_stats'; sudo docker stats --no-stream --format '{{json .}}' 2>/dev/null
+true"#;
+
+#[derive(Debug, Default, Serialize)]
+pub struct DockerDiskUsage {
+    pub kind: String,
+    pub total_count: u64,
+    pub active: u64,
+    pub size: u64,
+    pub reclaimable: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ContainerStats {
+    pub name: String,
+    pub cpu_percent: f64,
+    pub memory_used: u64,
+    pub memory_limit: u64,
+    pub memory_percent: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VmStats {
+    pub instance: String,
+    pub lima_instance: String,
+    pub cpus: u32,
+    pub load_average: [f64; 3],
+    pub memory_total: u64,
+    pub memory_used: u64,
+    pub disk_total: u64,
+    pub disk_used: u64,
+    pub docker_disk: Vec<DockerDiskUsage>,
+    pub containers: Vec<ContainerStats>,
+}
+
+fn parse_percent(value: &str) -> f64 {
+    value.trim().trim_end_matches('%').parse().unwrap_or(0.0)
+}
+
+/// docker のサイズ表記（"1.2GB", "800MB (66%)"）をバイト数に変換
+fn parse_docker_size(value: &str) -> u64 {
+    value
+        .split_whitespace()
+        .next()
+        .and_then(|v| utils::parse_size(v).ok())
+        .unwrap_or(0)
+}
+
+fn json_str<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+fn parse_docker_df_line(line: &str) -> Option<DockerDiskUsage> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    Some(DockerDiskUsage {
+        kind: json_str(&value, "Type").to_string(),
+        total_count: json_str(&value, "TotalCount").parse().unwrap_or(0),
+        active: json_str(&value, "Active").parse().unwrap_or(0),
+        size: parse_docker_size(json_str(&value, "Size")),
+        reclaimable: parse_docker_size(json_str(&value, "Reclaimable")),
+    })
+}
+
+fn parse_docker_stats_line(line: &str) -> Option<ContainerStats> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let mut usage = json_str(&value, "MemUsage").split('/');
+    Some(ContainerStats {
+        name: json_str(&value, "Name").to_string(),
+        cpu_percent: parse_percent(json_str(&value, "CPUPerc")),
+        memory_used: usage.next().map(parse_docker_size).unwrap_or(0),
+        memory_limit: usage.next().map(parse_docker_size).unwrap_or(0),
+        memory_percent: parse_percent(json_str(&value, "MemPerc")),
+    })
+}
+
+/// STATS_SCRIPT の出力をパース
+fn parse_stats_output(output: &str, stats: &mut VmStats) {
+    let mut section = "";
+    let mut mem_available = 0;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("@@") {
+            section = name;
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        match section {
+            "loadavg" => {
+                for (i, value) in line.split_whitespace().take(3).enumerate() {
+                    stats.load_average[i] = value.parse().unwrap_or(0.0);
+                }
+            }
+            "nproc" => stats.cpus = line.parse().unwrap_or(0),
+            "meminfo" => {
+                let kb: u64 = line
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                if line.starts_with("MemTotal:") {
+                    stats.memory_total = kb * 1024;
+                } else if line.starts_with("MemAvailable:") {
+                    mem_available = kb * 1024;
+                }
+            }
+            "df" => {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() >= 3 {
+                    stats.disk_total = fields[1].parse().unwrap_or(0);
+                    stats.disk_used = fields[2].parse().unwrap_or(0);
+                }
+            }
+            "docker_df" => stats.docker_disk.extend(parse_docker_df_line(line)),
+            "docker_stats" => stats.containers.extend(parse_docker_stats_line(line)),
+            _ => {}
+        }
+    }
+    stats.memory_used = stats.memory_total.saturating_sub(mem_available);
+}
+
+fn collect_stats(instance: &Instance) -> Result<VmStats> {
+    let output = lima::shell(&instance.lima_instance, &["bash", "-c", STATS_SCRIPT])?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "Failed to collect stats from {}: {}",
+            instance.lima_instance,
+            stderr.trim()
+        );
+    }
+
+    let mut stats = VmStats {
+        instance: instance.name.clone(),
+        lima_instance: instance.lima_instance.clone(),
+        ..Default::default()
+    };
+    parse_stats_output(&String::from_utf8_lossy(&output.stdout), &mut stats);
+    Ok(stats)
+}
+
+fn print_stats(stats: &VmStats) {
+    println!("=== {} ({}) ===", stats.instance, stats.lima_instance);
+    println!(
+        "CPU:    {} cores, load {:.2} {:.2} {:.2}",
+        stats.cpus, stats.load_average[0], stats.load_average[1], stats.load_average[2]
+    );
+    println!(
+        "Memory: {} / {}",
+        utils::format_size(stats.memory_used),
+        utils::format_size(stats.memory_total)
+    );
+    println!(
+        "Disk:   {} / {}",
+        utils::format_size(stats.disk_used),
+        utils::format_size(stats.disk_total)
+    );
+
+    if !stats.docker_disk.is_empty() {
+        println!("\n{:<16} {:>6} {:>6} {:>12} {:>12}", "DOCKER", "TOTAL", "ACTIVE", "SIZE", "RECLAIMABLE");
+        for usage in &stats.docker_disk {
+            println!(
+                "{:<16} {:>6} {:>6} {:>12} {:>12}",
+                usage.kind,
+                usage.total_count,
+                usage.active,
+                utils::format_size(usage.size),
+                utils::format_size(usage.reclaimable)
+            );
+        }
+    }
+
+    if !stats.containers.is_empty() {
+        println!("\n{:<40} {:>8} {:>24} {:>8}", "CONTAINER", "CPU %", "MEM USAGE / LIMIT", "MEM %");
+        for container in &stats.containers {
+            println!(
+                "{:<40} {:>7.2}% {:>24} {:>7.2}%",
+                container.name,
+                container.cpu_percent,
+                format!(
+                    "{} / {}",
+                    utils::format_size(container.memory_used),
+                    utils::format_size(container.memory_limit)
+                ),
+                container.memory_percent
+            );
+        }
+    }
+    println!();
+}
+
+/// インスタンス VM のリソース使用状況を表示
+pub fn execute(name: Option<&str>, all: bool, json: bool) -> Result<()> {
+    let main_repo = utils::resolve_main_repo()?;
+    let state = State::load(&main_repo)?;
+
+    let targets: Vec<&Instance> = if all {
+        state
+            .instances
+            .iter()
+            .filter(|inst| !inst.lima_instance.is_empty())
+            .collect()
+    } else {
+        vec![state.resolve_instance(name)?]
+    };
+
+    let mut results = Vec::new();
+    for instance in targets {
+        if lima::info(&instance.lima_instance)? != lima::InstanceStatus::Running {
+            if !all {
+                anyhow::bail!(
+                    "Lima VM '{}' is not running. Start it with 'fracta vm start {}'.",
+                    instance.lima_instance,
+                    instance.name
+                );
+            }
+            continue;
+        }
+        match collect_stats(instance) {
+            Ok(stats) => results.push(stats),
+            Err(e) if all => eprintln!("Warning: {}", e),
+            Err(e) => return Err(e),
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    if results.is_empty() {
+        println!("No running VMs.");
+    }
+    for stats in &results {
+        print_stats(stats);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats_output() {
+        let output = r#"@@loadavg
+0.52 0.40 0.31 1/234 5678
+@@nproc
+4
+@@meminfo
+MemTotal:        8000000 kB
+MemAvailable:    6000000 kB
+@@df
+/dev/vda1 52000000000 12000000000 40000000000 24% /
+@@docker_df
+{"Active":"2","Reclaimable":"800MB (66%)","Size":"1.2GB","TotalCount":"5","Type":"Images"}
+{"Active":"0","Reclaimable":"3.5GB","Size":"3.5GB","TotalCount":"40","Type":"Build Cache"}
+@@docker_stats
+{"CPUPerc":"12.50%","MemPerc":"1.30%","MemUsage":"100MiB / 7.6GiB","Name":"app-web-1"}
+"#;
+        let mut stats = VmStats::default();
+        parse_stats_output(output, &mut stats);
+
+        assert_eq!(stats.cpus, 4);
+        assert_eq!(stats.load_average, [0.52, 0.40, 0.31]);
+        assert_eq!(stats.memory_total, 8000000 * 1024);
+        assert_eq!(stats.memory_used, 2000000 * 1024);
+        assert_eq!(stats.disk_used, 12000000000);
+        assert_eq!(stats.docker_disk.len(), 2);
+        assert_eq!(stats.docker_disk[0].size, 1_200_000_000);
+        assert_eq!(stats.docker_disk[0].reclaimable, 800_000_000);
+        assert_eq!(stats.docker_disk[1].kind, "Build Cache");
+        assert_eq!(stats.containers.len(), 1);
+        assert_eq!(stats.containers[0].cpu_percent, 12.5);
+        assert_eq!(stats.containers[0].memory_used, 100 * 1024 * 1024);
+    }
+}
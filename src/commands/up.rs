@@ -2,11 +2,13 @@ use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
+use crate::commands;
 use crate::config;
 use crate::hooks::{self, HookContext};
 use crate::images;
 use crate::lima::client as lima;
 use crate::lima::template;
+use crate::preflight;
 use crate::state::State;
 use crate::utils;
 
@@ -16,11 +18,12 @@ pub fn execute(
     no_parallel_build: bool,
     vm_build_copy: bool,
     vm_build_dir: Option<&str>,
+    force: bool,
 ) -> Result<()> {
     let main_repo = utils::resolve_main_repo()?;
-    let state = State::load(&main_repo)?;
+    let mut state = State::load(&main_repo)?;
 
-    let instance = state.resolve_instance(name)?;
+    let instance = state.resolve_instance(name)?.clone();
     let instance_name = instance.name.as_str();
     let project_name = utils::sanitize_name(instance_name);
 
@@ -42,34 +45,47 @@ pub fn execute(
                 "Lima VM '{}' not found. Creating a new VM...",
                 instance.lima_instance
             );
-
-            let mut tmpl_cfg = template::TemplateConfig::new(
-                &worktree_path.to_string_lossy(),
-                config.vm_mount_type.as_deref(),
-                config.vm_user.as_deref(),
-            );
-            tmpl_cfg.resolve_template(config.vm_template.as_deref(), &main_repo, &worktree_path);
-            if let Some(scripts) = &config.vm_provision_scripts {
-                tmpl_cfg.load_provision_scripts(scripts, &main_repo)?;
-            }
+            let mut tmpl_cfg =
+                template::TemplateConfig::from_config(&config, &main_repo, &worktree_path)?;
+            tmpl_cfg.set_instance(&instance.name, &instance.branch);
             let temp_template = template::create_temp_template(&tmpl_cfg)?;
 
+            commands::vm::ensure_capacity(
+                &mut state,
+                &main_repo,
+                &config,
+                &instance,
+                preflight::resources_for_new(&tmpl_cfg, true)?,
+                force,
+            )?;
             lima::create(temp_template.path(), &instance.lima_instance)?;
             println!("Starting Lima VM: {}...", instance.lima_instance);
-            let timeout = config.vm_provision_timeout.as_deref().or(
-                if config.vm_provision_scripts.is_some() { Some("20m0s") } else { None }
-            );
-            lima::start_with_timeout(&instance.lima_instance, timeout)?;
+            lima::start_with_timeout(&instance.lima_instance, config.vm_start_timeout())?;
         }
         lima::InstanceStatus::Stopped => {
+            commands::vm::ensure_capacity(
+                &mut state,
+                &main_repo,
+                &config,
+                &instance,
+                preflight::resources_for_existing(&instance.lima_instance)?,
+                force,
+            )?;
             println!("Starting Lima VM: {}...", instance.lima_instance);
-            lima::start(&instance.lima_instance)?;
+            lima::start_with_timeout(&instance.lima_instance, config.vm_start_timeout())?;
         }
         lima::InstanceStatus::Running => {
             println!("Lima VM '{}' is already running.", instance.lima_instance);
         }
     }
 
+    // VM 間ネットワーク有効時は各 VM のホスト名を更新
+    if config.shared_vm_network() {
+        if let Err(e) = commands::net::sync_hosts(&state) {
+            eprintln!("Warning: Failed to update instance hostnames: {}", e);
+        }
+    }
+
     // compose ファイルの相対パスを取得
     let compose_rel = compose_base
         .strip_prefix(&worktree_path)
@@ -120,7 +136,8 @@ pub fn execute(
         if images.is_empty() {
             println!("No images found to sync.");
         } else {
-            images::sync_images_to_vm(&instance.lima_instance, &images)?;
+            let options = images::SyncOptions::from_config(&config)?;
+            images::sync_images_to_vm(&instance.lima_instance, &images, &options)?;
         }
     } else {
         println!("Image sync skipped (--no-sync-images).");
@@ -146,6 +163,9 @@ pub fn execute(
         anyhow::bail!("docker compose up failed in VM");
     }
 
+    state.record_compose_activity(instance_name)?;
+    state.save(&main_repo)?;
+
     hooks::run_hook("post_up", &worktree_path, &hook_ctx, &config)?;
 
     // コンテナの状態を表示
@@ -1,15 +1,17 @@
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
-use crate::config;
+use crate::commands::{self, idle};
+use crate::config::{self, Config};
 use crate::lima::client as lima;
-use crate::lima::{ssh, template};
+use crate::lima::{logs, ssh, template};
+use crate::preflight::{self, VmResources};
 use crate::state::{Instance, State};
 use crate::utils;
 
 /// 現在の worktree に Lima VM を追加する
-pub fn add_vm(name: Option<&str>) -> Result<()> {
+pub fn add_vm(name: Option<&str>, force: bool) -> Result<()> {
     if !lima::is_available() {
         anyhow::bail!("Lima is not installed. Please install lima first: brew install lima");
     }
@@ -75,6 +77,8 @@ pub fn add_vm(name: Option<&str>) -> Result<()> {
             active_forwards: Vec::new(),
             active_proxy: None,
             active_browser: None,
+            last_used_at: None,
+            last_compose_at: None,
         };
         state.add_instance(instance);
         (instance_name, cwd.clone())
@@ -96,19 +100,20 @@ pub fn add_vm(name: Option<&str>) -> Result<()> {
 
     // Lima テンプレートを生成
     println!("Creating Lima VM template...");
-    let mut template_config = template::TemplateConfig::new(
-        &worktree_path.to_string_lossy(),
-        config.vm_mount_type.as_deref(),
-        config.vm_user.as_deref(),
-    );
-    template_config.resolve_template(config.vm_template.as_deref(), &main_repo, &worktree_path);
-    if let Some(scripts) = &config.vm_provision_scripts {
-        template_config.load_provision_scripts(scripts, &main_repo)?;
+    let mut template_config =
+        template::TemplateConfig::from_config(&config, &main_repo, &worktree_path)?;
+    if let Some(inst) = state.find_instance(&instance_name) {
+        template_config.set_instance(&inst.name, &inst.branch);
     }
     let temp_template = template::create_temp_template(&template_config)?;
 
     // Lima VM を作成
     println!("Creating Lima VM: {}...", lima_instance);
+    preflight::check(
+        &lima_instance,
+        preflight::resources_for_new(&template_config, false)?,
+        force,
+    )?;
     lima::create(temp_template.path(), &lima_instance)?;
 
     // state を更新
@@ -129,10 +134,10 @@ pub fn add_vm(name: Option<&str>) -> Result<()> {
     Ok(())
 }
 
-pub fn start(name: Option<&str>) -> Result<()> {
+pub fn start(name: Option<&str>, force: bool) -> Result<()> {
     let main_repo = utils::resolve_main_repo()?;
-    let state = State::load(&main_repo)?;
-    let instance = state.resolve_instance(name)?;
+    let mut state = State::load(&main_repo)?;
+    let instance = state.resolve_instance(name)?.clone();
 
     let info = lima::info(&instance.lima_instance)?;
     match info {
@@ -147,12 +152,31 @@ pub fn start(name: Option<&str>) -> Result<()> {
             println!("Lima VM '{}' is already running.", instance.lima_instance);
         }
         lima::InstanceStatus::Stopped => {
+            let config = config::load_config(&main_repo, Some(Path::new(&instance.path)))?;
+            ensure_capacity(
+                &mut state,
+                &main_repo,
+                &config,
+                &instance,
+                preflight::resources_for_existing(&instance.lima_instance)?,
+                force,
+            )?;
             println!("Starting Lima VM: {}...", instance.lima_instance);
-            lima::start(&instance.lima_instance)?;
+            lima::start_with_timeout(&instance.lima_instance, config.vm_start_timeout())?;
             println!("Lima VM started.");
+
+            // VM 間ネットワーク有効時は各 VM のホスト名を更新
+            if config.shared_vm_network() {
+                if let Err(e) = commands::net::sync_hosts(&state) {
+                    eprintln!("Warning: Failed to update instance hostnames: {}", e);
+                }
+            }
         }
     }
 
+    state.touch_instance(&instance.name)?;
+    state.save(&main_repo)?;
+
     Ok(())
 }
 
@@ -160,6 +184,123 @@ pub fn stop(name: Option<&str>) -> Result<()> {
     let main_repo = utils::resolve_main_repo()?;
     let mut state = State::load(&main_repo)?;
     let instance = state.resolve_instance(name)?.clone();
+
+    stop_instance(&mut state, &main_repo, &instance)
+}
+
+/// target 以外で起動中の VM（最終利用が古い順、不明なものを先頭に）
+fn running_others(state: &State, target: &Instance) -> Result<Vec<(Option<u64>, Instance)>> {
+    let mut running: Vec<(Option<u64>, Instance)> = Vec::new();
+    for inst in &state.instances {
+        if inst.name == target.name || inst.lima_instance.is_empty() {
+            continue;
+        }
+        if lima::info(&inst.lima_instance)? == lima::InstanceStatus::Running {
+            running.push((idle::last_active_at(inst), inst.clone()));
+        }
+    }
+    Ok(lru_order(running, &target.name))
+}
+
+/// 起動中の VM を最終利用が古い順に並べる（不明なものを先頭、同時刻は元の順、target は除く）
+fn lru_order(
+    mut running: Vec<(Option<u64>, Instance)>,
+    target: &str,
+) -> Vec<(Option<u64>, Instance)> {
+    running.retain(|(_, inst)| inst.name != target);
+    running.sort_by_key(|(last_active, _)| *last_active);
+    running
+}
+
+/// target を起動しても max_running_vms に収まるよう停止する VM を選ぶ（LRU 順）
+fn select_lru_evictions(
+    running: Vec<(Option<u64>, Instance)>,
+    target: &str,
+    max: usize,
+) -> Vec<(Option<u64>, Instance)> {
+    let ordered = lru_order(running, target);
+    let excess = (ordered.len() + 1).saturating_sub(max);
+    ordered.into_iter().take(excess).collect()
+}
+
+/// target を起動すると max_running_vms を超えるか
+pub fn would_exceed_capacity(state: &State, config: &Config, target: &Instance) -> Result<bool> {
+    match config.max_running_vms {
+        Some(max) => Ok(running_others(state, target)?.len() >= max),
+        None => Ok(false),
+    }
+}
+
+/// 起動中の VM 数が max_running_vms に達していれば、LRU の VM を停止するか起動を拒否する
+///
+/// ホスト資源の確認（停止予定の VM を除く）が通るまでは VM を停止しない。
+pub fn ensure_capacity(
+    state: &mut State,
+    main_repo: &Path,
+    config: &Config,
+    target: &Instance,
+    resources: VmResources,
+    force: bool,
+) -> Result<()> {
+    let max = match config.max_running_vms {
+        Some(max) => max,
+        None => return preflight::check(&target.lima_instance, resources, force),
+    };
+    let running = running_others(state, target)?;
+    if running.len() < max {
+        return preflight::check(&target.lima_instance, resources, force);
+    }
+
+    let now = utils::unix_now();
+    let describe = |last_active: Option<u64>| {
+        last_active
+            .map(|at| {
+                format!(
+                    "idle {}",
+                    utils::format_duration(std::time::Duration::from_secs(now.saturating_sub(at)))
+                )
+            })
+            .unwrap_or_else(|| "last use unknown".to_string())
+    };
+
+    match config.vm_eviction.as_deref().unwrap_or("refuse") {
+        "lru" => {
+            let evictions = select_lru_evictions(running, &target.name, max);
+            let evicting: Vec<&str> = evictions
+                .iter()
+                .map(|(_, inst)| inst.lima_instance.as_str())
+                .collect();
+            preflight::check_before_evicting(&target.lima_instance, resources, force, &evicting)?;
+
+            for (last_active, inst) in &evictions {
+                println!(
+                    "max_running_vms ({}) reached. Stopping least recently used VM: {} ({})",
+                    max,
+                    inst.name,
+                    describe(*last_active)
+                );
+                stop_instance(state, main_repo, inst)?;
+            }
+            Ok(())
+        }
+        "refuse" => {
+            let candidates: Vec<String> = running
+                .iter()
+                .map(|(last_active, inst)| format!("  {} ({})", inst.name, describe(*last_active)))
+                .collect();
+            anyhow::bail!(
+                "Cannot start '{}': max_running_vms ({}) reached.\nStop one of these VMs first (least recently used first):\n{}\nOr set vm_eviction = \"lru\" to stop them automatically.",
+                target.name,
+                max,
+                candidates.join("\n")
+            )
+        }
+        other => anyhow::bail!("Unsupported vm_eviction '{}'. Use lru or refuse.", other),
+    }
+}
+
+/// フォワード・SOCKS5 プロキシ・ブラウザを後片付けしてから Lima VM を停止する
+pub fn stop_instance(state: &mut State, main_repo: &Path, instance: &Instance) -> Result<()> {
     let instance_name = instance.name.clone();
 
     // Stop local helper processes first so state/ports stay consistent.
@@ -198,7 +339,7 @@ pub fn stop(name: Option<&str>) -> Result<()> {
         }
     }
     state.remove_browser(&instance_name)?;
-    state.save(&main_repo)?;
+    state.save(main_repo)?;
 
     let info = lima::info(&instance.lima_instance)?;
     if info == lima::InstanceStatus::Running {
@@ -220,8 +361,8 @@ pub fn shell(
     command: &[String],
 ) -> Result<()> {
     let main_repo = utils::resolve_main_repo()?;
-    let state = State::load(&main_repo)?;
-    let instance = state.resolve_instance(name)?;
+    let mut state = State::load(&main_repo)?;
+    let instance = state.resolve_instance(name)?.clone();
     let name = instance.name.as_str();
 
     let info = lima::info(&instance.lima_instance)?;
@@ -243,6 +384,9 @@ pub fn shell(
         lima::InstanceStatus::Running => {}
     }
 
+    state.touch_instance(name)?;
+    state.save(&main_repo)?;
+
     println!("Connecting to Lima VM: {}...", instance.lima_instance);
     println!("Worktree path: {}", instance.path);
     println!("---");
@@ -290,8 +434,8 @@ pub fn list() -> Result<()> {
 
     println!("=== VM Instances ===");
     println!(
-        "{:<20} {:<25} {:<10} {}",
-        "NAME", "LIMA VM", "VM STATUS", "PATH"
+        "{:<20} {:<25} {:<10} PATH",
+        "NAME", "LIMA VM", "VM STATUS"
     );
     println!("{}", "-".repeat(90));
 
@@ -310,10 +454,172 @@ pub fn list() -> Result<()> {
     Ok(())
 }
 
-/// デフォルトの Lima テンプレートを stdout に出力
-pub fn template() -> Result<()> {
+/// VM の起動ログ・provision ログを表示
+pub fn logs(name: Option<&str>, follow: bool, provision: bool, lines: usize) -> Result<()> {
+    let main_repo = utils::resolve_main_repo()?;
+    let state = State::load(&main_repo)?;
+    let instance = state.resolve_instance(name)?;
+
+    let info = lima::info(&instance.lima_instance)?;
+    if info == lima::InstanceStatus::NotFound {
+        anyhow::bail!(
+            "Lima VM '{}' not found. Run 'fracta add {}' first.",
+            instance.lima_instance,
+            instance.name
+        );
+    }
+
+    if provision {
+        if info != lima::InstanceStatus::Running {
+            anyhow::bail!(
+                "Lima VM '{}' is not running. Provision logs live inside the VM; showing host logs requires omitting --provision.",
+                instance.lima_instance
+            );
+        }
+        return logs::show_provision_log(&instance.lima_instance, lines, follow);
+    }
+
+    logs::show_host_logs(&instance.lima_instance, lines, follow)
+}
+
+/// 設定を読まずにデフォルトの Lima テンプレートを stdout に出力
+fn print_default_template() -> Result<()> {
     let config = template::TemplateConfig::new("{{WORKTREE_PATH}}", None, None);
-    let content = template::generate_default(&config);
-    print!("{}", content);
+    print!("{}", template::generate_default(&config));
+    Ok(())
+}
+
+/// インスタンス用に解決した Lima テンプレートを stdout に出力
+///
+/// 名前を省略すると現在の worktree のインスタンスを使い、worktree 外ではデフォルトテンプレートを出力する。
+pub fn template(name: Option<&str>, validate: bool, diff: bool) -> Result<()> {
+    // worktree 外（リポジトリ外、またはどのインスタンスにも属さない場所）でのみデフォルトを出力
+    let use_default = name.is_none() && !validate && !diff;
+    let main_repo = match utils::resolve_main_repo() {
+        Ok(main_repo) => main_repo,
+        Err(_) if use_default => return print_default_template(),
+        Err(e) => return Err(e),
+    };
+    let state = State::load(&main_repo)?;
+    let instance = match name {
+        Some(_) => state.resolve_instance(name)?.clone(),
+        None => match state.current_instance()? {
+            Some(instance) => instance.clone(),
+            None if use_default => return print_default_template(),
+            None => anyhow::bail!("No instance found for current directory"),
+        },
+    };
+    let worktree_path = PathBuf::from(&instance.path);
+
+    let config = config::load_config(&main_repo, Some(&worktree_path))?;
+    let mut template_config =
+        template::TemplateConfig::from_config(&config, &main_repo, &worktree_path)?;
+    template_config.set_instance(&instance.name, &instance.branch);
+    let content = template::generate(&template_config)?;
+
+    if !validate && !diff {
+        print!("{}", content);
+        return Ok(());
+    }
+
+    let temp_template = template::write_temp_template(&content)?;
+
+    if validate {
+        lima::validate(temp_template.path())?;
+        println!("Template for '{}' is valid.", instance.name);
+    }
+
+    if diff {
+        let created = lima::instance_dir(&instance.lima_instance).join("lima.yaml");
+        if !created.exists() {
+            anyhow::bail!(
+                "No template found for Lima VM '{}' ({}). Has the VM been created?",
+                instance.lima_instance,
+                created.display()
+            );
+        }
+        let status = Command::new("diff")
+            .arg("-u")
+            .args(["--label", &format!("{} (VM)", created.display())])
+            .args(["--label", &format!("{} (resolved)", instance.name)])
+            .arg(&created)
+            .arg(temp_template.path())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .context("Failed to execute diff")?;
+        match status.code() {
+            Some(0) => println!("No differences from the template of '{}'.", instance.lima_instance),
+            Some(1) => {}
+            _ => anyhow::bail!("diff exited with error"),
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(name: &str) -> Instance {
+        Instance {
+            name: name.to_string(),
+            path: format!("/repo/{}", name),
+            branch: name.to_string(),
+            lima_instance: format!("fracta-{}", name),
+            active_forwards: Vec::new(),
+            active_proxy: None,
+            active_browser: None,
+            last_used_at: None,
+            last_compose_at: None,
+        }
+    }
+
+    fn names(selected: &[(Option<u64>, Instance)]) -> Vec<&str> {
+        selected.iter().map(|(_, inst)| inst.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_select_lru_evictions() {
+        let running = vec![
+            (Some(300), instance("c")),
+            (Some(100), instance("a")),
+            (None, instance("unknown")),
+            (Some(200), instance("b")),
+        ];
+
+        // 最終利用が不明なものを先頭に、古い順
+        assert_eq!(names(&lru_order(running.clone(), "target")), vec!["unknown", "a", "b", "c"]);
+
+        // 4 台起動中で max 4 なら 1 台、max 2 なら 3 台停止
+        assert_eq!(names(&select_lru_evictions(running.clone(), "target", 4)), vec!["unknown"]);
+        assert_eq!(
+            names(&select_lru_evictions(running.clone(), "target", 2)),
+            vec!["unknown", "a", "b"]
+        );
+        // 上限に達していなければ停止しない
+        assert!(select_lru_evictions(running.clone(), "target", 5).is_empty());
+        assert!(select_lru_evictions(running, "target", 10).is_empty());
+    }
+
+    #[test]
+    fn test_select_lru_evictions_ties_and_target() {
+        let running = vec![
+            (Some(100), instance("first")),
+            (Some(100), instance("second")),
+            (Some(50), instance("target")),
+        ];
+
+        // 起動対象は最も古くても選ばない
+        let ordered = lru_order(running.clone(), "target");
+        assert_eq!(names(&ordered), vec!["first", "second"]);
+
+        // 同時刻は元の順
+        assert_eq!(names(&select_lru_evictions(running.clone(), "target", 2)), vec!["first"]);
+        assert_eq!(
+            names(&select_lru_evictions(running, "target", 1)),
+            vec!["first", "second"]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::lima::template;
+use crate::utils;
+
+fn images_dir() -> Result<PathBuf> {
+    Ok(utils::fracta_home_dir()?.join("images"))
+}
+
+fn ensure_images_dir() -> Result<PathBuf> {
+    let dir = images_dir()?;
+    fs::create_dir_all(&dir).context("Failed to create image directory")?;
+    Ok(dir)
+}
+
+/// ファイル名から arch を推測（見つからなければホストの arch）
+fn guess_arch(file_name: &str) -> &'static str {
+    let lower = file_name.to_lowercase();
+    if lower.contains("arm64") || lower.contains("aarch64") {
+        "aarch64"
+    } else if lower.contains("amd64") || lower.contains("x86_64") {
+        "x86_64"
+    } else {
+        template::host_arch()
+    }
+}
+
+fn file_name_from(location: &str) -> Result<String> {
+    let name = location
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if name.is_empty() {
+        anyhow::bail!("Cannot determine image file name from '{}'. Use --name.", location);
+    }
+    Ok(name)
+}
+
+/// 保存先に同名のイメージがあれば --force なしでは上書きしない
+fn check_destination(dest: &Path, force: bool) -> Result<()> {
+    if dest.exists() && !force {
+        anyhow::bail!(
+            "Image already exists: {}. Use --force to overwrite it or --name to store it under another name.",
+            dest.display()
+        );
+    }
+    Ok(())
+}
+
+fn sidecar_path(image_path: &Path) -> PathBuf {
+    let mut name = image_path.as_os_str().to_os_string();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// 一時ファイルを検証してイメージ置き場に配置する
+fn store_verified(tmp_path: &Path, dest: &Path, digest: Option<&str>) -> Result<String> {
+    println!("Verifying image...");
+    let actual = utils::sha256_file(tmp_path)?;
+    if let Some(expected) = digest {
+        let expected = utils::parse_digest(expected)?;
+        if actual != expected {
+            let _ = fs::remove_file(tmp_path);
+            anyhow::bail!(
+                "Digest mismatch for {}: expected sha256:{}, got sha256:{}",
+                dest.display(),
+                expected,
+                actual
+            );
+        }
+    }
+
+    fs::rename(tmp_path, dest).context("Failed to store image")?;
+    fs::write(sidecar_path(dest), format!("{}\n", actual))
+        .context("Failed to write image checksum")?;
+    Ok(actual)
+}
+
+fn print_config_snippet(path: &Path, arch: &str, digest: &str) {
+    println!("\nImage stored: {}", path.display());
+    println!("\nAdd this to fracta.toml to use it:");
+    println!("[[vm_images]]");
+    println!("location = \"file://{}\"", path.display());
+    println!("arch = \"{}\"", arch);
+    println!("digest = \"sha256:{}\"", digest);
+}
+
+/// URL からイメージをダウンロードして ~/.fracta/images に保存
+pub fn fetch(
+    url: &str,
+    digest: Option<&str>,
+    arch: Option<&str>,
+    name: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    if let Some(digest) = digest {
+        utils::parse_digest(digest)?;
+    }
+    let file_name = match name {
+        Some(name) => name.to_string(),
+        None => file_name_from(url)?,
+    };
+    let dest = ensure_images_dir()?.join(&file_name);
+    check_destination(&dest, force)?;
+    let tmp_path = dest.with_extension("download");
+
+    println!("Downloading {}...", url);
+    let status = Command::new("curl")
+        .args(["-fL", "--progress-bar", "-o"])
+        .arg(&tmp_path)
+        .arg(url)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Failed to execute curl")?;
+
+    if !status.success() {
+        let _ = fs::remove_file(&tmp_path);
+        anyhow::bail!("Failed to download {}", url);
+    }
+
+    let actual = store_verified(&tmp_path, &dest, digest)?;
+    print_config_snippet(&dest, arch.unwrap_or(guess_arch(&file_name)), &actual);
+    Ok(())
+}
+
+/// ローカルのイメージファイルを ~/.fracta/images に取り込む
+pub fn import(
+    path: &str,
+    digest: Option<&str>,
+    arch: Option<&str>,
+    name: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    if let Some(digest) = digest {
+        utils::parse_digest(digest)?;
+    }
+    let src = utils::expand_home(path);
+    if !src.is_file() {
+        anyhow::bail!("Image file not found: {}", src.display());
+    }
+    let file_name = match name {
+        Some(name) => name.to_string(),
+        None => file_name_from(&src.to_string_lossy())?,
+    };
+    let dest = ensure_images_dir()?.join(&file_name);
+    check_destination(&dest, force)?;
+    let tmp_path = dest.with_extension("import");
+
+    println!("Importing {}...", src.display());
+    fs::copy(&src, &tmp_path).context("Failed to copy image")?;
+
+    let actual = store_verified(&tmp_path, &dest, digest)?;
+    print_config_snippet(&dest, arch.unwrap_or(guess_arch(&file_name)), &actual);
+    Ok(())
+}
+
+/// 保存済みイメージ一覧を表示
+pub fn list() -> Result<()> {
+    let dir = images_dir()?;
+    let mut entries = Vec::new();
+    if dir.exists() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+            if !path.is_file() || matches!(ext, "sha256" | "download" | "import") {
+                continue;
+            }
+            entries.push(path);
+        }
+    }
+    entries.sort();
+
+    if entries.is_empty() {
+        println!("No stored VM images.");
+        return Ok(());
+    }
+
+    println!("{:<50} {:>10} DIGEST", "IMAGE", "SIZE");
+    println!("{}", "-".repeat(90));
+    for path in entries {
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let digest = fs::read_to_string(sidecar_path(&path))
+            .map(|d| format!("sha256:{}", d.trim()))
+            .unwrap_or_else(|_| "(unverified)".to_string());
+        println!(
+            "{:<50} {:>7.1} MB {}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            size as f64 / 1_048_576.0,
+            digest
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("base.img");
+        assert!(check_destination(&dest, false).is_ok());
+
+        std::fs::write(&dest, b"image").unwrap();
+        let err = check_destination(&dest, false).unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert!(check_destination(&dest, true).is_ok());
+    }
+
+    #[test]
+    fn test_file_name_from() {
+        assert_eq!(
+            file_name_from("https://example.com/img/ubuntu-arm64.img?x=1").unwrap(),
+            "ubuntu-arm64.img"
+        );
+        assert_eq!(guess_arch("ubuntu-24.04-server-cloudimg-arm64.img"), "aarch64");
+        assert_eq!(guess_arch("ubuntu-24.04-server-cloudimg-amd64.img"), "x86_64");
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Default)]
@@ -8,14 +9,137 @@ pub struct Config {
     pub compose_parallel_build: Option<bool>,
     pub vm_mount_type: Option<String>,
     pub vm_user: Option<String>,
+    pub vm_type: Option<String>,
+    pub vm_arch: Option<String>,
     pub vm_build_copy: Option<bool>,
     pub vm_build_dir: Option<String>,
     pub vm_template: Option<String>,
-    pub vm_provision_scripts: Option<Vec<String>>,
+    pub vm_provision_scripts: Option<Vec<ProvisionScript>>,
     pub vm_provision_timeout: Option<String>,
+    pub vm_network_check: Option<NetworkCheckConfig>,
+    pub vm_probes: Option<Vec<VmProbe>>,
+    pub vm_docker: Option<VmDocker>,
+    pub vm_images: Option<Vec<VmImage>>,
+    pub vm_mounts: Option<Vec<VmMount>>,
+    pub vm_shared_caches: Option<Vec<String>>,
+    pub vm_network: Option<String>,
+    pub idle_stop_after: Option<String>,
+    pub max_running_vms: Option<usize>,
+    pub vm_eviction: Option<String>,
+    pub vm_prune_level: Option<String>,
+    pub image_sync_jobs: Option<usize>,
+    pub image_sync_mode: Option<String>,
+    pub image_registry_port: Option<u16>,
+    pub image_cache_max_size: Option<String>,
+    pub image_sync: Option<ImageSyncConfig>,
+    pub vm: Option<VmSection>,
     pub hooks: Option<HookCommands>,
 }
 
+/// VM のベースイメージ（Lima の images エントリ）
+#[derive(Debug, Clone, Deserialize)]
+pub struct VmImage {
+    /// URL、ローカルパス、または file:// URL
+    pub location: String,
+    pub arch: Option<String>,
+    /// 例: "sha256:..."
+    pub digest: Option<String>,
+}
+
+/// vm_provision_scripts のエントリ（パス文字列またはテーブル）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ProvisionScript {
+    Path(String),
+    Table(ProvisionScriptTable),
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct ProvisionScriptTable {
+    /// スクリプトファイル（相対パスはメインリポジトリ基準）
+    pub path: Option<String>,
+    /// インラインのスクリプト本文（path と排他）
+    pub script: Option<String>,
+    /// Lima の provision モード（system / user / boot / dependency、デフォルト: system）
+    pub mode: Option<String>,
+    /// スクリプトに渡す環境変数
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// 実行タイミング（once: 初回のみ（デフォルト）、always: 起動毎）
+    pub run: Option<String>,
+}
+
+/// provision 前のネットワーク疎通確認（"none"、ホスト名、またはテーブル）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum NetworkCheckConfig {
+    Simple(String),
+    Table(NetworkCheckTable),
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct NetworkCheckTable {
+    /// 名前解決と TCP 接続を確認するホスト（host または host:port、デフォルトのポートは 80）
+    pub host: Option<String>,
+    /// 成功するまで繰り返すコマンド（host と排他）
+    pub command: Option<String>,
+    /// 待つ時間（例: "2m"、デフォルト: 5m）
+    pub timeout: Option<String>,
+}
+
+/// VM 起動時に成功するまで待つ readiness probe
+#[derive(Debug, Clone, Deserialize)]
+pub struct VmProbe {
+    /// VM 内で実行するスクリプト（成功するまで繰り返す）
+    pub script: String,
+    /// 失敗時に表示するヒント
+    pub hint: Option<String>,
+    /// 待つ時間（例: "2m"、デフォルト: 5m）
+    pub timeout: Option<String>,
+}
+
+/// [vm_docker] テーブル: VM への Docker Engine のインストール
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct VmDocker {
+    /// インストールするか（テーブルがあればデフォルトで true）
+    pub enable: Option<bool>,
+    /// Docker Engine のバージョン（例: "27.3.1"、省略時は最新）
+    pub version: Option<String>,
+    /// compose プラグインのバージョン（例: "2.29.7"、省略時は最新）
+    pub compose_version: Option<String>,
+    /// /etc/docker/daemon.json の内容
+    pub daemon: Option<toml::Value>,
+}
+
+/// worktree 以外に VM へマウントするホストディレクトリ
+#[derive(Debug, Clone, Deserialize)]
+pub struct VmMount {
+    /// ホスト側のパス（~/ 可、相対パスはメインリポジトリ基準）
+    pub location: String,
+    /// ゲスト側のパス（省略時は location と同じ）
+    pub mount_point: Option<String>,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+/// [image_sync] テーブル: `fracta up` で同期するイメージの選択
+#[derive(Debug, Deserialize, Default)]
+pub struct ImageSyncConfig {
+    /// 同期するサービス名・イメージ名の glob（省略時はすべて）
+    pub include: Option<Vec<String>>,
+    /// 同期しないサービス名・イメージ名の glob（include より優先）
+    pub exclude: Option<Vec<String>>,
+    /// ホストにないイメージの扱い（skip / pull-host / pull-vm / fail、デフォルト: skip）
+    pub missing: Option<String>,
+}
+
+/// [vm] テーブル
+#[derive(Debug, Deserialize, Default)]
+pub struct VmSection {
+    /// 生成されるデフォルトテンプレートに深くマージする部分テンプレート
+    pub template_overlay: Option<toml::Value>,
+}
+
 #[derive(Debug, Deserialize, Default)]
 pub struct HookCommands {
     pub pre_add: Option<String>,
@@ -35,6 +159,25 @@ impl Config {
         self.compose_base.as_deref().unwrap_or("docker-compose.yml")
     }
 
+    /// VM 間ネットワークが有効か（vm_network が isolated 以外）
+    pub fn shared_vm_network(&self) -> bool {
+        !matches!(self.vm_network.as_deref().map(str::trim), None | Some("") | Some("isolated"))
+    }
+
+    /// limactl start の --timeout
+    /// 未指定でも provision スクリプト・probe・Docker 導入がある場合は長めの 20m0s にする
+    pub fn vm_start_timeout(&self) -> Option<&str> {
+        if let Some(timeout) = self.vm_provision_timeout.as_deref() {
+            return Some(timeout);
+        }
+        let docker = self
+            .vm_docker
+            .as_ref()
+            .is_some_and(|docker| docker.enable.unwrap_or(true));
+        (docker || self.vm_provision_scripts.is_some() || self.vm_probes.is_some())
+            .then_some("20m0s")
+    }
+
     pub fn hook_command(&self, hook: &str) -> Option<&str> {
         let hooks = self.hooks.as_ref()?;
         match hook {
@@ -85,6 +228,26 @@ fn config_paths_in_dir(dir: &Path) -> Result<Vec<PathBuf>> {
     Ok(paths)
 }
 
+fn merge_vm(target: &mut Option<VmSection>, incoming: VmSection) {
+    let dst = target.get_or_insert_with(VmSection::default);
+    if incoming.template_overlay.is_some() {
+        dst.template_overlay = incoming.template_overlay;
+    }
+}
+
+fn merge_image_sync(target: &mut Option<ImageSyncConfig>, incoming: ImageSyncConfig) {
+    let dst = target.get_or_insert_with(ImageSyncConfig::default);
+    if incoming.include.is_some() {
+        dst.include = incoming.include;
+    }
+    if incoming.exclude.is_some() {
+        dst.exclude = incoming.exclude;
+    }
+    if incoming.missing.is_some() {
+        dst.missing = incoming.missing;
+    }
+}
+
 fn merge_hooks(target: &mut Option<HookCommands>, incoming: HookCommands) {
     let dst = target.get_or_insert_with(HookCommands::default);
     if incoming.pre_add.is_some() {
@@ -132,6 +295,12 @@ fn merge_config(target: &mut Config, incoming: Config) {
     if incoming.vm_user.is_some() {
         target.vm_user = incoming.vm_user;
     }
+    if incoming.vm_type.is_some() {
+        target.vm_type = incoming.vm_type;
+    }
+    if incoming.vm_arch.is_some() {
+        target.vm_arch = incoming.vm_arch;
+    }
     if incoming.vm_build_copy.is_some() {
         target.vm_build_copy = incoming.vm_build_copy;
     }
@@ -144,9 +313,60 @@ fn merge_config(target: &mut Config, incoming: Config) {
     if incoming.vm_provision_scripts.is_some() {
         target.vm_provision_scripts = incoming.vm_provision_scripts;
     }
+    if incoming.vm_network_check.is_some() {
+        target.vm_network_check = incoming.vm_network_check;
+    }
+    if incoming.vm_probes.is_some() {
+        target.vm_probes = incoming.vm_probes;
+    }
+    if incoming.vm_docker.is_some() {
+        target.vm_docker = incoming.vm_docker;
+    }
     if incoming.vm_provision_timeout.is_some() {
         target.vm_provision_timeout = incoming.vm_provision_timeout;
     }
+    if incoming.vm_images.is_some() {
+        target.vm_images = incoming.vm_images;
+    }
+    if incoming.vm_mounts.is_some() {
+        target.vm_mounts = incoming.vm_mounts;
+    }
+    if incoming.vm_shared_caches.is_some() {
+        target.vm_shared_caches = incoming.vm_shared_caches;
+    }
+    if incoming.vm_network.is_some() {
+        target.vm_network = incoming.vm_network;
+    }
+    if incoming.idle_stop_after.is_some() {
+        target.idle_stop_after = incoming.idle_stop_after;
+    }
+    if incoming.max_running_vms.is_some() {
+        target.max_running_vms = incoming.max_running_vms;
+    }
+    if incoming.vm_eviction.is_some() {
+        target.vm_eviction = incoming.vm_eviction;
+    }
+    if incoming.vm_prune_level.is_some() {
+        target.vm_prune_level = incoming.vm_prune_level;
+    }
+    if incoming.image_sync_jobs.is_some() {
+        target.image_sync_jobs = incoming.image_sync_jobs;
+    }
+    if incoming.image_sync_mode.is_some() {
+        target.image_sync_mode = incoming.image_sync_mode;
+    }
+    if incoming.image_registry_port.is_some() {
+        target.image_registry_port = incoming.image_registry_port;
+    }
+    if incoming.image_cache_max_size.is_some() {
+        target.image_cache_max_size = incoming.image_cache_max_size;
+    }
+    if let Some(image_sync) = incoming.image_sync {
+        merge_image_sync(&mut target.image_sync, image_sync);
+    }
+    if let Some(vm) = incoming.vm {
+        merge_vm(&mut target.vm, vm);
+    }
     if let Some(hooks) = incoming.hooks {
         merge_hooks(&mut target.hooks, hooks);
     }
@@ -170,5 +390,11 @@ pub fn load_config(main_repo: &Path, worktree_path: Option<&Path>) -> Result<Con
         merge_config(&mut config, incoming);
     }
 
+    if config.max_running_vms == Some(0) {
+        anyhow::bail!(
+            "max_running_vms must be at least 1 (remove it to allow any number of running VMs)"
+        );
+    }
+
     Ok(config)
 }
@@ -0,0 +1,649 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::lima::ssh;
+use crate::utils;
+
+/// 今回の同期で使われず、この期間使われていないエントリを削除
+pub const CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60); // 7 days
+
+const INDEX_FILE: &str = "index.json";
+const ARCHIVE_SUFFIX: &str = ".tar.gz";
+const LOCK_SUFFIX: &str = ".lock";
+const TEMP_PREFIX: &str = ".fracta-tmp-";
+
+/// 書き込み中のプロセスが生きていても、これより古いロックは放棄されたものとみなす
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// 中断された書き込みの一時ファイルを削除するまでの時間
+const TEMP_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(utils::fracta_home_dir()?.join("cache"))
+}
+
+/// image ID からキャッシュのキー（ファイル名）を取得
+///
+/// 短縮すると別イメージと衝突しうるため、digest 全体を使う。
+pub fn cache_key(image_id: &str) -> String {
+    // image_id is like "sha256:abcdef1234..."
+    let hash = image_id.strip_prefix("sha256:").unwrap_or(image_id);
+    hash.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+pub fn cache_path(image_id: &str) -> Result<PathBuf> {
+    Ok(cache_dir()?.join(format!("{}{}", cache_key(image_id), ARCHIVE_SUFFIX)))
+}
+
+/// tarball の SHA-256 を記録するサイドカーファイル
+fn checksum_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// docker save の manifest.json を保存するサイドカーファイル（レイヤー単位の転送に使う）
+fn manifest_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(".manifest.json");
+    PathBuf::from(name)
+}
+
+/// キャッシュエントリの manifest.json に記録されたレイヤーのパス（アーカイブ内）
+///
+/// manifest を記録していない古いエントリでは None。
+pub fn layer_paths(image_id: &str) -> Result<Option<Vec<String>>> {
+    let path = manifest_path(&cache_path(image_id)?);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+    let manifest: serde_json::Value = serde_json::from_str(&content)
+        .context(format!("Failed to parse {}", path.display()))?;
+    let layers = manifest
+        .get(0)
+        .and_then(|entry| entry.get("Layers"))
+        .and_then(|v| v.as_array())
+        .context(format!("{} does not contain Layers", path.display()))?;
+    Ok(Some(
+        layers
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+    ))
+}
+
+/// キャッシュエントリの検証結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrity {
+    Missing,
+    Valid,
+    /// tarball とチェックサムが一致しない、またはチェックサムがない
+    Corrupt,
+}
+
+fn verify_archive(archive: &Path) -> Result<Integrity> {
+    if !archive.exists() {
+        return Ok(Integrity::Missing);
+    }
+    let expected = match fs::read_to_string(checksum_path(archive)) {
+        Ok(content) => content.trim().to_lowercase(),
+        Err(_) => return Ok(Integrity::Corrupt),
+    };
+    if utils::sha256_file(archive)? == expected {
+        Ok(Integrity::Valid)
+    } else {
+        Ok(Integrity::Corrupt)
+    }
+}
+
+/// キャッシュエントリをチェックサムで検証
+pub fn verify(image_id: &str) -> Result<Integrity> {
+    verify_archive(&cache_path(image_id)?)
+}
+
+/// 一時ファイルの書き込み先（エントリ毎・プロセス毎に一意）
+pub fn temp_file() -> Result<tempfile::NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix(TEMP_PREFIX)
+        .tempfile_in(ensure_cache_dir()?)
+        .context("Failed to create temp cache file")
+}
+
+/// サイドカーファイルを一時ファイル経由で書き込む
+fn write_sidecar(path: &Path, content: &[u8]) -> Result<()> {
+    let mut sidecar = tempfile::Builder::new()
+        .prefix(TEMP_PREFIX)
+        .tempfile_in(ensure_cache_dir()?)
+        .context("Failed to create temp cache file")?;
+    sidecar
+        .write_all(content)
+        .context(format!("Failed to write {}", path.display()))?;
+    sidecar
+        .persist(path)
+        .context(format!("Failed to store {}", path.display()))?;
+    Ok(())
+}
+
+/// 書き終えた一時ファイルをチェックサム・manifest.json と合わせて配置
+///
+/// サイドカーを先に書くため、tarball が見えた時点で検証できる。
+pub fn store(
+    tmp: tempfile::NamedTempFile,
+    image_id: &str,
+    manifest: Option<&[u8]>,
+) -> Result<PathBuf> {
+    let path = cache_path(image_id)?;
+    let checksum = utils::sha256_file(tmp.path())?;
+
+    write_sidecar(&checksum_path(&path), format!("{}\n", checksum).as_bytes())?;
+    match manifest {
+        Some(manifest) => write_sidecar(&manifest_path(&path), manifest)?,
+        None => {
+            let _ = fs::remove_file(manifest_path(&path));
+        }
+    }
+    tmp.persist(&path).context("Failed to rename cache file")?;
+    Ok(path)
+}
+
+/// ロックファイルによるプロセス間のロック（drop で解放）
+pub struct EntryLock {
+    path: PathBuf,
+}
+
+impl Drop for EntryLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// 書き込んだプロセスが終了しているか、古すぎるロックか
+fn is_stale_lock(path: &Path) -> bool {
+    let pid = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.trim().parse::<u32>().ok());
+    let age = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .unwrap_or_default();
+    match pid {
+        Some(pid) => !ssh::is_process_alive(pid) || age > LOCK_STALE_AFTER,
+        // 書き込み途中の可能性があるので、少し待ってから判断する
+        None => age > LOCK_POLL_INTERVAL * 4,
+    }
+}
+
+/// ロックを取得するまで待つ（waiting_message は待ち始めたときに一度だけ表示）
+fn acquire_lock(path: &Path, waiting_message: Option<&str>) -> Result<EntryLock> {
+    let mut waiting = false;
+    loop {
+        match fs::OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                write!(file, "{}", std::process::id()).context("Failed to write cache lock")?;
+                return Ok(EntryLock {
+                    path: path.to_path_buf(),
+                });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if is_stale_lock(path) {
+                    let _ = fs::remove_file(path);
+                    continue;
+                }
+                if !waiting {
+                    if let Some(message) = waiting_message {
+                        println!("{}", message);
+                    }
+                    waiting = true;
+                }
+                std::thread::sleep(LOCK_POLL_INTERVAL);
+            }
+            Err(e) => {
+                return Err(e).context(format!("Failed to create lock {}", path.display()))
+            }
+        }
+    }
+}
+
+/// ロックを取得できればすぐ返す（他のプロセスが保持していれば None）
+fn try_acquire_lock(path: &Path) -> Result<Option<EntryLock>> {
+    for _ in 0..2 {
+        match fs::OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                write!(file, "{}", std::process::id()).context("Failed to write cache lock")?;
+                return Ok(Some(EntryLock {
+                    path: path.to_path_buf(),
+                }));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if !is_stale_lock(path) {
+                    return Ok(None);
+                }
+                let _ = fs::remove_file(path);
+            }
+            Err(e) => {
+                return Err(e).context(format!("Failed to create lock {}", path.display()))
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn entry_lock_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}{}", key, LOCK_SUFFIX))
+}
+
+/// エントリのロックを取得（他のプロセスが使用中なら待つ）
+///
+/// 書き込みだけでなく読み出し（docker load）が終わるまで保持し、その間は削除されない。
+pub fn lock_entry(image_id: &str) -> Result<EntryLock> {
+    let dir = ensure_cache_dir()?;
+    acquire_lock(
+        &entry_lock_path(&dir, &cache_key(image_id)),
+        Some("Waiting for another fracta process to finish using the image cache..."),
+    )
+}
+
+/// index.json の読み書き（load → 更新 → save）をプロセス間で直列化するロック
+fn lock_index() -> Result<EntryLock> {
+    let dir = ensure_cache_dir()?;
+    acquire_lock(&dir.join(format!("{}{}", INDEX_FILE, LOCK_SUFFIX)), None)
+}
+
+pub fn ensure_cache_dir() -> Result<PathBuf> {
+    let dir = cache_dir()?;
+    fs::create_dir_all(&dir).context("Failed to create cache directory")?;
+    Ok(dir)
+}
+
+/// キャッシュの tarball の隣に置く index.json
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    #[serde(default)]
+    entries: BTreeMap<String, IndexEntry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct IndexEntry {
+    image_id: String,
+    #[serde(default)]
+    images: Vec<String>,
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    last_used: u64,
+}
+
+fn load_index() -> Result<CacheIndex> {
+    let path = cache_dir()?.join(INDEX_FILE);
+    if !path.exists() {
+        return Ok(CacheIndex::default());
+    }
+    let content = fs::read_to_string(&path)
+        .context(format!("Failed to read {}", path.display()))?;
+    // 壊れた index は作り直す（tarball 自体は残っている）
+    Ok(serde_json::from_str(&content).unwrap_or_default())
+}
+
+/// index.json を書き出す（呼び出し側で lock_index を保持すること）
+fn save_index(index: &CacheIndex) -> Result<()> {
+    let dir = ensure_cache_dir()?;
+    let content = serde_json::to_string_pretty(index).context("Failed to serialize cache index")?;
+    let mut tmp = tempfile::Builder::new()
+        .prefix(TEMP_PREFIX)
+        .tempfile_in(&dir)
+        .context("Failed to create temp cache index")?;
+    tmp.write_all(content.as_bytes())
+        .context("Failed to write cache index")?;
+    tmp.persist(dir.join(INDEX_FILE))
+        .context("Failed to write cache index")?;
+    Ok(())
+}
+
+/// エントリの利用を記録（イメージ名と最終利用時刻）
+pub fn touch(image_id: &str, images: &[String]) -> Result<()> {
+    let _lock = lock_index()?;
+    let mut index = load_index()?;
+    let now = utils::unix_now();
+    let entry = index
+        .entries
+        .entry(cache_key(image_id))
+        .or_insert_with(|| IndexEntry {
+            image_id: image_id.to_string(),
+            created_at: now,
+            ..Default::default()
+        });
+    let mut names: BTreeSet<String> = entry.images.drain(..).collect();
+    names.extend(images.iter().cloned());
+    entry.images = names.into_iter().collect();
+    entry.last_used = now;
+    save_index(&index)
+}
+
+/// キャッシュの 1 エントリ
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub key: String,
+    pub size: u64,
+    pub images: Vec<String>,
+    /// 最終利用時刻（UNIX 秒、index にない場合はファイルの更新時刻）
+    pub last_used: u64,
+}
+
+/// キャッシュディレクトリの tarball を index の情報と合わせて列挙
+pub fn entries() -> Result<Vec<CacheEntry>> {
+    let dir = cache_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let index = load_index()?;
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let key = match path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(ARCHIVE_SUFFIX))
+        {
+            Some(key) => key.to_string(),
+            None => continue,
+        };
+
+        let metadata = entry.metadata()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let info = index.entries.get(&key);
+        entries.push(CacheEntry {
+            size: metadata.len(),
+            images: info.map(|i| i.images.clone()).unwrap_or_default(),
+            last_used: info.map(|i| i.last_used).filter(|t| *t > 0).unwrap_or(modified),
+            key,
+        });
+    }
+    entries.sort_by(|a, b| b.last_used.cmp(&a.last_used).then(a.key.cmp(&b.key)));
+    Ok(entries)
+}
+
+/// 削除するエントリを選ぶ
+///
+/// 1. keep に含まれず max_age より古いもの
+/// 2. 合計が max_size を超える間、keep 以外を最終利用が古い順に
+pub fn select_evictions(
+    entries: &[CacheEntry],
+    keep: &HashSet<String>,
+    now: u64,
+    max_age: Option<Duration>,
+    max_size: Option<u64>,
+) -> Vec<String> {
+    let mut candidates: Vec<&CacheEntry> = entries
+        .iter()
+        .filter(|e| !keep.contains(&e.key))
+        .collect();
+    candidates.sort_by_key(|e| e.last_used);
+
+    let mut evicted = Vec::new();
+    let mut total: u64 = entries.iter().map(|e| e.size).sum();
+    for entry in candidates {
+        let stale = max_age
+            .map(|age| now.saturating_sub(entry.last_used) > age.as_secs())
+            .unwrap_or(false);
+        let over = max_size.map(|max| total > max).unwrap_or(false);
+        if stale || over {
+            total -= entry.size;
+            evicted.push(entry.key.clone());
+        }
+    }
+    evicted
+}
+
+/// remove / clear の結果
+#[derive(Debug, Default)]
+pub struct Removed {
+    pub bytes: u64,
+    pub entries: usize,
+    /// 他のプロセスが使用中のためスキップしたエントリ数
+    pub in_use: usize,
+}
+
+/// エントリのファイルと index の情報を削除（呼び出し側でエントリのロックを保持すること）
+fn delete_entries(keys: &[&str]) -> Result<Removed> {
+    let mut removed = Removed::default();
+    if keys.is_empty() {
+        return Ok(removed);
+    }
+    let _lock = lock_index()?;
+    let dir = cache_dir()?;
+    let mut index = load_index()?;
+    for key in keys {
+        let path = dir.join(format!("{}{}", key, ARCHIVE_SUFFIX));
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let _ = fs::remove_file(checksum_path(&path));
+        let _ = fs::remove_file(manifest_path(&path));
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                removed.bytes += size;
+                removed.entries += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                eprintln!("Warning: failed to remove {}: {}", path.display(), e);
+                continue;
+            }
+        }
+        index.entries.remove(*key);
+    }
+    save_index(&index)?;
+    Ok(removed)
+}
+
+/// エントリを削除（他のプロセスが使用中のエントリはスキップ）
+pub fn remove(keys: &[String]) -> Result<Removed> {
+    if keys.is_empty() {
+        return Ok(Removed::default());
+    }
+    let dir = ensure_cache_dir()?;
+    let mut locks = Vec::new();
+    let mut unlocked = Vec::new();
+    let mut in_use = 0;
+    for key in keys {
+        match try_acquire_lock(&entry_lock_path(&dir, key))? {
+            Some(lock) => {
+                locks.push(lock);
+                unlocked.push(key.as_str());
+            }
+            None => in_use += 1,
+        }
+    }
+    let mut removed = delete_entries(&unlocked)?;
+    removed.in_use = in_use;
+    Ok(removed)
+}
+
+/// 壊れたエントリを削除（lock_entry で取得したロックを保持したまま）
+pub fn discard(image_id: &str, _lock: &EntryLock) -> Result<()> {
+    delete_entries(&[&cache_key(image_id)]).map(|_| ())
+}
+
+/// 使用中でないすべてのエントリを削除し、すべて消せた場合は index も削除
+pub fn clear() -> Result<Removed> {
+    let keys: Vec<String> = entries()?.into_iter().map(|e| e.key).collect();
+    let removed = remove(&keys)?;
+    if removed.in_use == 0 {
+        let _lock = lock_index()?;
+        let index_path = cache_dir()?.join(INDEX_FILE);
+        if index_path.exists() {
+            fs::remove_file(&index_path).context("Failed to remove cache index")?;
+        }
+    }
+    Ok(removed)
+}
+
+/// 中断された書き込みの一時ファイルを削除
+fn remove_stale_temp_files() -> Result<()> {
+    let dir = cache_dir()?;
+    if !dir.exists() {
+        return Ok(());
+    }
+    let now = SystemTime::now();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let is_temp = entry
+            .file_name()
+            .to_str()
+            .map(|name| name.starts_with(TEMP_PREFIX) || name.ends_with(".tar.gz.tmp"))
+            .unwrap_or(false);
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| now.duration_since(t).ok())
+            .unwrap_or_default();
+        if is_temp && age > TEMP_MAX_AGE {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// 同期後の掃除: 古いエントリの削除と image_cache_max_size の適用
+pub fn enforce(used_keys: &HashSet<String>, max_size: Option<u64>) -> Result<()> {
+    remove_stale_temp_files()?;
+    let current = entries()?;
+    let evicted = select_evictions(
+        &current,
+        used_keys,
+        utils::unix_now(),
+        Some(CACHE_MAX_AGE),
+        max_size,
+    );
+    let removed = remove(&evicted)?;
+
+    if removed.entries > 0 {
+        println!(
+            "Cache cleanup: removed {} of stale images ({} entr{})",
+            utils::format_size(removed.bytes),
+            removed.entries,
+            if removed.entries == 1 { "y" } else { "ies" }
+        );
+    }
+    if let Some(max) = max_size {
+        let total: u64 = entries()?.iter().map(|e| e.size).sum();
+        if total > max {
+            eprintln!(
+                "Warning: image cache is {} (limit {}) because the remaining entries are in use",
+                utils::format_size(total),
+                utils::format_size(max)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, size: u64, last_used: u64) -> CacheEntry {
+        CacheEntry {
+            key: key.to_string(),
+            size,
+            images: Vec::new(),
+            last_used,
+        }
+    }
+
+    #[test]
+    fn test_cache_key_uses_full_digest() {
+        let id = format!("sha256:{}", "ab12".repeat(16));
+        assert_eq!(cache_key(&id), "ab12".repeat(16));
+        assert_ne!(
+            cache_key(&format!("sha256:{}0", "a".repeat(63))),
+            cache_key(&format!("sha256:{}1", "a".repeat(63)))
+        );
+        assert_eq!(cache_key("sha256:../../etc"), "etc");
+    }
+
+    #[test]
+    fn test_verify_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("abc.tar.gz");
+        assert_eq!(verify_archive(&archive).unwrap(), Integrity::Missing);
+
+        fs::write(&archive, "archive").unwrap();
+        assert_eq!(verify_archive(&archive).unwrap(), Integrity::Corrupt);
+
+        let checksum = utils::sha256_file(&archive).unwrap();
+        fs::write(checksum_path(&archive), format!("{}\n", checksum)).unwrap();
+        assert_eq!(verify_archive(&archive).unwrap(), Integrity::Valid);
+
+        fs::write(&archive, "truncated").unwrap();
+        assert_eq!(verify_archive(&archive).unwrap(), Integrity::Corrupt);
+    }
+
+    #[test]
+    fn test_acquire_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc.lock");
+
+        let lock = acquire_lock(&path, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        assert!(!is_stale_lock(&path));
+        // 使用中のエントリは削除側が取得できない
+        assert!(try_acquire_lock(&path).unwrap().is_none());
+        drop(lock);
+        assert!(!path.exists());
+        assert!(try_acquire_lock(&path).unwrap().is_some());
+
+        // 終了したプロセスのロックは奪える
+        fs::write(&path, "999999999").unwrap();
+        assert!(is_stale_lock(&path));
+        let _lock = acquire_lock(&path, None).unwrap();
+    }
+
+    #[test]
+    fn test_select_evictions() {
+        let day = 24 * 60 * 60;
+        let now = 30 * day;
+        let entries = vec![
+            entry("old", 100, now - 10 * day),
+            entry("lru", 300, now - 2 * day),
+            entry("recent", 400, now - day),
+            entry("used", 500, now - 20 * day),
+        ];
+        let keep: HashSet<String> = ["used".to_string()].into_iter().collect();
+
+        // 期限切れのみ（使用中は残す）
+        assert_eq!(
+            select_evictions(&entries, &keep, now, Some(CACHE_MAX_AGE), None),
+            vec!["old"]
+        );
+        // サイズ上限: 古い順に上限以下になるまで
+        assert_eq!(
+            select_evictions(&entries, &keep, now, Some(CACHE_MAX_AGE), Some(1000)),
+            vec!["old", "lru"]
+        );
+        assert_eq!(
+            select_evictions(&entries, &HashSet::new(), now, None, Some(0)),
+            vec!["used", "old", "lru", "recent"]
+        );
+        assert!(select_evictions(&entries, &keep, now, None, None).is_empty());
+    }
+}
@@ -0,0 +1,1337 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub mod cache;
+mod tar;
+
+use self::cache::{cache_key, cache_path};
+use crate::config::Config;
+use crate::lima::client as lima;
+use crate::lima::ssh;
+use crate::utils;
+
+/// image_sync_jobs のデフォルト（同時に save / load するイメージ数）
+pub const DEFAULT_SYNC_JOBS: usize = 4;
+
+/// registry モードでホストに起動するレジストリ
+const REGISTRY_CONTAINER: &str = "fracta-registry";
+const REGISTRY_IMAGE: &str = "registry:2";
+const DEFAULT_REGISTRY_PORT: u16 = 5050;
+
+/// イメージを VM に届ける方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// docker save → キャッシュ → docker load（デフォルト）
+    Load,
+    /// ホストのローカルレジストリに push し、VM から pull
+    Registry,
+}
+
+impl SyncMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "load" => Ok(SyncMode::Load),
+            "registry" => Ok(SyncMode::Registry),
+            other => anyhow::bail!(
+                "Unsupported image_sync_mode '{}' (expected load or registry)",
+                other
+            ),
+        }
+    }
+}
+
+/// ホストにないイメージの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingImagePolicy {
+    /// 同期しない（VM 内の compose が pull する）
+    Skip,
+    /// ホストで pull してから同期
+    PullHost,
+    /// VM 内で直接 pull
+    PullVm,
+    /// エラーにする
+    Fail,
+}
+
+impl MissingImagePolicy {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "skip" => Ok(MissingImagePolicy::Skip),
+            "pull-host" => Ok(MissingImagePolicy::PullHost),
+            "pull-vm" => Ok(MissingImagePolicy::PullVm),
+            "fail" => Ok(MissingImagePolicy::Fail),
+            other => anyhow::bail!(
+                "Unsupported image_sync.missing '{}' (expected skip, pull-host, pull-vm or fail)",
+                other
+            ),
+        }
+    }
+}
+
+/// イメージ同期の設定
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    pub jobs: usize,
+    pub mode: SyncMode,
+    pub registry_port: u16,
+    /// キャッシュの合計サイズの上限（バイト）
+    pub cache_max_size: Option<u64>,
+    /// 同期するサービス名・イメージ名の glob（空ならすべて）
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub missing: MissingImagePolicy,
+}
+
+impl SyncOptions {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mode = match config.image_sync_mode.as_deref() {
+            Some(value) => SyncMode::parse(value)?,
+            None => SyncMode::Load,
+        };
+        let cache_max_size = config
+            .image_cache_max_size
+            .as_deref()
+            .map(utils::parse_size)
+            .transpose()
+            .context("Invalid image_cache_max_size")?;
+        let selection = config.image_sync.as_ref();
+        let missing = match selection.and_then(|s| s.missing.as_deref()) {
+            Some(value) => MissingImagePolicy::parse(value)?,
+            None => MissingImagePolicy::Skip,
+        };
+        Ok(Self {
+            jobs: config.image_sync_jobs.unwrap_or(DEFAULT_SYNC_JOBS),
+            mode,
+            registry_port: config.image_registry_port.unwrap_or(DEFAULT_REGISTRY_PORT),
+            cache_max_size,
+            include: selection.and_then(|s| s.include.clone()).unwrap_or_default(),
+            exclude: selection.and_then(|s| s.exclude.clone()).unwrap_or_default(),
+            missing,
+        })
+    }
+
+    /// include / exclude に合うか（サービス名かイメージ名のどちらかに一致すればよい）
+    fn selects(&self, image: &ComposeImage) -> bool {
+        let matches = |patterns: &[String]| {
+            patterns.iter().any(|pattern| {
+                utils::glob_match(pattern, &image.service) || utils::glob_match(pattern, &image.image)
+            })
+        };
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+fn docker_compose_config(compose_base: &Path, worktree_path: &Path) -> Result<Value> {
+    let compose_path = compose_base
+        .to_str()
+        .context("Compose base path is not valid UTF-8")?;
+    let output = Command::new("docker")
+        .args(["compose", "-f", compose_path, "config", "--format", "json"])
+        .current_dir(worktree_path)
+        .output()
+        .context("Failed to execute docker compose config")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("docker compose config failed: {}", stderr.trim());
+    }
+
+    let value: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse compose config JSON")?;
+    Ok(value)
+}
+
+fn default_project_name(worktree_path: &Path) -> Result<String> {
+    let name = worktree_path
+        .file_name()
+        .context("Failed to determine worktree directory name")?
+        .to_string_lossy()
+        .to_string();
+    Ok(utils::sanitize_name(&name))
+}
+
+/// compose のサービスが参照するイメージ
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ComposeImage {
+    pub service: String,
+    pub image: String,
+    /// build セクションがある（ホストになくても VM 内で compose がビルドする）
+    pub build: bool,
+}
+
+pub fn collect_compose_images(
+    compose_base: &Path,
+    worktree_path: &Path,
+) -> Result<Vec<ComposeImage>> {
+    let config = docker_compose_config(compose_base, worktree_path)?;
+    let project = config
+        .get("name")
+        .and_then(|v| v.as_str())
+        .map(utils::sanitize_name)
+        .unwrap_or(default_project_name(worktree_path)?);
+
+    let mut images = BTreeSet::new();
+    let services = config
+        .get("services")
+        .and_then(|v| v.as_object())
+        .context("Compose config does not contain services")?;
+
+    for (service_name, service_cfg) in services {
+        let image = match service_cfg.get("image").and_then(|v| v.as_str()) {
+            Some(image) => image.to_string(),
+            None => format!("{}-{}", project, service_name),
+        };
+        images.insert(ComposeImage {
+            service: service_name.clone(),
+            image,
+            build: service_cfg.get("build").is_some(),
+        });
+    }
+
+    Ok(images.into_iter().collect())
+}
+
+/// `image<TAB>id` 形式の出力をパース（ID が空のものは存在しない）
+fn parse_image_ids(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (image, id) = line.split_once('\t')?;
+            let id = id.trim();
+            (!id.is_empty()).then(|| (image.to_string(), id.to_string()))
+        })
+        .collect()
+}
+
+/// 複数イメージの ID を 1 回で調べるスクリプト（引数にイメージ名を渡す）
+///
+/// 存在しないイメージ（"No such image"）は ID を空で出力し、それ以外の失敗は
+/// エラー出力をそのまま出して非ゼロで終了する。
+fn image_ids_script(docker: &str) -> String {
+    format!(
+        r#"for image in "$@"; do
+  if out=$({docker} image inspect --format '{{{{.Id}}}}' "$image" 2>&1); then
+    printf '%s\t%s\n' "$image" "$out"
+  else
+    case "$out" in
+      *"No such image"*) printf '%s\t\n' "$image" ;;
+      *) printf '%s\n' "$out" >&2; exit 1 ;;
+    esac
+  fi
+done"#,
+        docker = docker
+    )
+}
+
+/// image_ids_script の実行結果を確認してパース
+fn image_ids_from_output(output: &Output, location: &str) -> Result<HashMap<String, String>> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Failed to inspect images on {}: {}", location, stderr.trim());
+    }
+    Ok(parse_image_ids(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// ホストのイメージ ID を一括取得
+fn host_image_ids(images: &[String]) -> Result<HashMap<String, String>> {
+    let output = Command::new("bash")
+        .args(["-c", &image_ids_script("docker"), "fracta"])
+        .args(images)
+        .output()
+        .context("Failed to execute docker image inspect")?;
+    image_ids_from_output(&output, "host")
+}
+
+/// VM 内のイメージ ID を一括取得（limactl shell 1 回）
+fn vm_image_ids(instance_name: &str, images: &[String]) -> Result<HashMap<String, String>> {
+    let script = image_ids_script("sudo docker");
+    let mut args = vec!["bash", "-c", script.as_str(), "fracta"];
+    args.extend(images.iter().map(String::as_str));
+    let output = lima::shell(instance_name, &args)?;
+    image_ids_from_output(&output, instance_name)
+}
+
+/// `docker image inspect --format '{{json .RootFS.Layers}}'` の出力（1 行 1 イメージ）をパース
+fn parse_layer_lines(output: &str) -> Vec<Vec<String>> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Vec<String>>(line.trim()).ok())
+        .filter(|layers| !layers.is_empty())
+        .collect()
+}
+
+/// ホストのイメージのレイヤー（diff ID）を一括取得
+fn host_image_layers(image_ids: &[&str]) -> Result<HashMap<String, Vec<String>>> {
+    let output = Command::new("docker")
+        .args(["image", "inspect", "--format", "{{.Id}} {{json .RootFS.Layers}}"])
+        .args(image_ids)
+        .output()
+        .context("Failed to execute docker image inspect")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (id, layers) = line.split_once(' ')?;
+            let layers: Vec<String> = serde_json::from_str(layers).ok()?;
+            Some((id.to_string(), layers))
+        })
+        .collect())
+}
+
+/// VM 内の Docker のレイヤー情報
+struct VmLayers {
+    /// containerd のイメージストアを使っているか
+    ///
+    /// containerd ストアの docker load は既存のレイヤーを補わないため、レイヤーを省いたアーカイブは読めない。
+    containerd_store: bool,
+    /// VM 内の全イメージのレイヤー列
+    chains: Vec<Vec<String>>,
+}
+
+/// `driver-status <json>` 行とレイヤー行からなる出力をパース
+fn parse_vm_layers(output: &str) -> VmLayers {
+    let containerd_store = output.lines().any(|line| {
+        line.strip_prefix("driver-status ")
+            .is_some_and(|status| status.contains("io.containerd.snapshotter"))
+    });
+    VmLayers {
+        containerd_store,
+        chains: parse_layer_lines(output),
+    }
+}
+
+/// VM のイメージストアと全イメージのレイヤー列を取得（limactl shell 1 回）
+fn vm_layers(instance_name: &str) -> Result<VmLayers> {
+    let script = "echo \"driver-status $(sudo docker info --format '{{json .DriverStatus}}' 2>/dev/null)\"; ids=$(sudo docker images -q | sort -u); [ -n \"$ids\" ] && sudo docker image inspect --format '{{json .RootFS.Layers}}' $ids 2>/dev/null; true";
+    let output = lima::shell(instance_name, &["bash", "-c", script])?;
+    Ok(parse_vm_layers(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// VM にすでにある先頭からのレイヤー数
+///
+/// レイヤーは親からの積み重ねなので、VM 内のいずれかのイメージと先頭から一致する部分だけを再利用できる。
+fn shared_layer_count(layers: &[String], vm_chains: &[Vec<String>]) -> usize {
+    vm_chains
+        .iter()
+        .map(|chain| {
+            chain
+                .iter()
+                .zip(layers)
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// docker save | gzip してキャッシュに保存
+///
+/// 流れてくる tar から manifest.json を取り出し、レイヤー単位の転送用に記録する。
+fn save_to_cache(images: &[String], image_id: &str, _lock: &cache::EntryLock) -> Result<PathBuf> {
+    let mut save = Command::new("docker")
+        .arg("save")
+        .args(images)
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run docker save")?;
+
+    let save_stdout = save
+        .stdout
+        .take()
+        .context("Failed to capture docker save output")?;
+
+    // Write to a unique temp file first, then rename for atomicity
+    let tmp_file = cache::temp_file()?;
+    let output = tmp_file
+        .as_file()
+        .try_clone()
+        .context("Failed to open temp cache file")?;
+
+    let mut gzip = Command::new("gzip")
+        .args(["-1"]) // fast compression
+        .stdin(Stdio::piped())
+        .stdout(Stdio::from(output))
+        .spawn()
+        .context("Failed to run gzip")?;
+
+    let gzip_stdin = gzip.stdin.take().context("Failed to open gzip input")?;
+    let copied = tar::copy(
+        BufReader::new(save_stdout),
+        BufWriter::new(gzip_stdin),
+        |_| true,
+        Some("manifest.json"),
+    );
+
+    let gzip_status = gzip.wait().context("Failed to wait for gzip")?;
+    let save_status = save.wait().context("Failed to wait for docker save")?;
+
+    if !save_status.success() || !gzip_status.success() {
+        anyhow::bail!("docker save | gzip failed for image {}", images.join(", "));
+    }
+    let copied = copied.context(format!("Failed to read docker save output for {}", images.join(", ")))?;
+
+    cache::store(tmp_file, image_id, copied.captured.as_deref())
+}
+
+/// VM 内の docker load（アーカイブは stdin から渡す）
+fn docker_load(instance_name: &str) -> Command {
+    let mut load = Command::new("limactl");
+    load.args([
+        "shell", "--workdir", "/", instance_name, "--", "sudo", "docker", "load", "-q",
+    ]);
+    load
+}
+
+/// アーカイブを出力するコマンドの stdout を VM 内の docker load に流す
+fn pipe_to_docker_load(instance_name: &str, mut source: Command, description: &str) -> Result<()> {
+    let mut source = source
+        .stdout(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to run {}", description))?;
+
+    let source_stdout = source
+        .stdout
+        .take()
+        .context(format!("Failed to capture {} output", description))?;
+
+    let load = docker_load(instance_name)
+        .stdin(Stdio::from(source_stdout))
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run docker load in VM")?;
+
+    // 並列実行時に出力が混ざらないよう、エラー出力は失敗時にまとめて表示
+    let load_output = load
+        .wait_with_output()
+        .context("Failed to wait for docker load")?;
+    let source_status = source
+        .wait()
+        .context(format!("Failed to wait for {}", description))?;
+
+    if !source_status.success() {
+        anyhow::bail!("{} failed", description);
+    }
+    if !load_output.status.success() {
+        let stderr = String::from_utf8_lossy(&load_output.stderr);
+        anyhow::bail!("docker load failed in VM: {}", stderr.trim());
+    }
+
+    Ok(())
+}
+
+/// キャッシュから VM に load
+fn load_from_cache(instance_name: &str, cache_file: &Path) -> Result<()> {
+    let mut gunzip = Command::new("gunzip");
+    gunzip.arg("-c").arg(cache_file);
+    pipe_to_docker_load(
+        instance_name,
+        gunzip,
+        &format!("gunzip {}", cache_file.display()),
+    )
+}
+
+/// VM にすでにある先頭 skip 個のレイヤーの、アーカイブ内のパス
+///
+/// paths は manifest.json の Layers。後続のレイヤーと同じパスは残す。
+fn shared_layer_paths(paths: &[String], layers: &[String], skip: usize) -> Result<HashSet<String>> {
+    if paths.len() != layers.len() {
+        anyhow::bail!(
+            "manifest.json has {} layers but the image has {}",
+            paths.len(),
+            layers.len()
+        );
+    }
+    let kept: HashSet<&String> = paths[skip..].iter().collect();
+    Ok(paths[..skip]
+        .iter()
+        .filter(|path| !kept.contains(path))
+        .cloned()
+        .collect())
+}
+
+/// キャッシュのアーカイブから skip_paths のレイヤーを除きながら load（戻り値: 省いたバイト数）
+///
+/// 展開せずに tar のエントリ単位で流す。docker load は既存のレイヤーのファイルを読まないため、
+/// manifest.json と config はそのまま残す。
+fn load_missing_layers(
+    instance_name: &str,
+    cache_file: &Path,
+    skip_paths: &HashSet<String>,
+) -> Result<u64> {
+    let mut gunzip = Command::new("gunzip")
+        .arg("-c")
+        .arg(cache_file)
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run gunzip")?;
+    let gunzip_stdout = gunzip
+        .stdout
+        .take()
+        .context("Failed to capture gunzip output")?;
+
+    let mut load = docker_load(instance_name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run docker load in VM")?;
+    let load_stdin = load.stdin.take().context("Failed to open docker load input")?;
+
+    let copied = tar::copy(
+        BufReader::new(gunzip_stdout),
+        BufWriter::new(load_stdin),
+        |path| !skip_paths.contains(path),
+        None,
+    );
+
+    let load_output = load
+        .wait_with_output()
+        .context("Failed to wait for docker load")?;
+    let gunzip_status = gunzip.wait().context("Failed to wait for gunzip")?;
+
+    if !load_output.status.success() {
+        let stderr = String::from_utf8_lossy(&load_output.stderr);
+        anyhow::bail!("docker load failed in VM: {}", stderr.trim());
+    }
+    if !gunzip_status.success() {
+        anyhow::bail!("gunzip {} failed", cache_file.display());
+    }
+    Ok(copied?.skipped_bytes)
+}
+
+/// 同じイメージ ID を持つイメージ（タグ違い）をまとめて転送する単位
+struct SyncJob {
+    image_id: String,
+    images: Vec<String>,
+    /// ホストのイメージのレイヤー（diff ID）
+    layers: Vec<String>,
+    /// VM にすでにある先頭からのレイヤー数
+    shared_layers: usize,
+}
+
+/// 1 つの転送の結果
+struct SyncOutcome {
+    /// 転送方法（"from cache" / "saved" / "registry"）
+    method: &'static str,
+    /// 転送を省いたレイヤー数とバイト数
+    skipped_layers: usize,
+    skipped_bytes: u64,
+}
+
+/// ホストと VM の ID を比較し、転送が必要なものをイメージ ID ごとにまとめる
+fn plan_sync(
+    images: &[String],
+    host_ids: &HashMap<String, String>,
+    vm_ids: &HashMap<String, String>,
+) -> (Vec<SyncJob>, Vec<String>, Vec<String>) {
+    let mut jobs: Vec<SyncJob> = Vec::new();
+    let mut missing = Vec::new();
+    let mut synced = Vec::new();
+
+    for image in images {
+        let host_id = match host_ids.get(image) {
+            Some(id) => id,
+            None => {
+                missing.push(image.clone());
+                continue;
+            }
+        };
+        if vm_ids.get(image) == Some(host_id) {
+            synced.push(image.clone());
+            continue;
+        }
+        match jobs.iter_mut().find(|job| &job.image_id == host_id) {
+            Some(job) => job.images.push(image.clone()),
+            None => jobs.push(SyncJob {
+                image_id: host_id.clone(),
+                images: vec![image.clone()],
+                layers: Vec::new(),
+                shared_layers: 0,
+            }),
+        }
+    }
+
+    (jobs, missing, synced)
+}
+
+/// ダイジェスト指定の参照（"redis@sha256:..."）か
+fn is_digest_ref(image: &str) -> bool {
+    image.contains('@')
+}
+
+/// VM 内でタグを付け直すコマンド（ダイジェスト指定の参照にはタグを付けられないため除く）
+fn tag_commands(image_id: &str, images: &[String]) -> Vec<String> {
+    images
+        .iter()
+        .filter(|image| !is_digest_ref(image))
+        .map(|image| format!("sudo docker tag {} '{}'", image_id, image))
+        .collect()
+}
+
+/// VM 内でタグを付け直す（キャッシュ作成時と異なるタグでも参照できるように）
+fn tag_in_vm(instance_name: &str, job: &SyncJob) -> Result<()> {
+    let script = tag_commands(&job.image_id, &job.images);
+    if script.is_empty() {
+        return Ok(());
+    }
+    let output = lima::shell(instance_name, &["bash", "-c", &script.join(" && ")])?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("docker tag failed in VM: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// 1 つのイメージ ID をキャッシュ経由で VM に転送
+fn sync_image(instance_name: &str, job: &SyncJob) -> Result<SyncOutcome> {
+    let cached = cache_path(&job.image_id)?;
+    // ロードが終わるまでロックを保持し、他のプロセスの書き込みや削除と重ならないようにする
+    let lock = cache::lock_entry(&job.image_id)?;
+    let from_cache = match cache::verify(&job.image_id)? {
+        cache::Integrity::Valid => true,
+        cache::Integrity::Corrupt => {
+            eprintln!(
+                "Warning: cached archive for {} is corrupt, re-creating it",
+                job.images.join(", ")
+            );
+            cache::discard(&job.image_id, &lock)?;
+            false
+        }
+        cache::Integrity::Missing => false,
+    };
+    if !from_cache {
+        save_to_cache(&job.images, &job.image_id, &lock)?;
+    }
+
+    let mut outcome = SyncOutcome {
+        method: if from_cache { "from cache" } else { "saved" },
+        skipped_layers: 0,
+        skipped_bytes: 0,
+    };
+    // manifest を記録していない古いエントリは丸ごと送る
+    let layer_paths = if job.shared_layers > 0 {
+        cache::layer_paths(&job.image_id).unwrap_or(None)
+    } else {
+        None
+    };
+    let partial = if let Some(paths) = layer_paths {
+        let result = shared_layer_paths(&paths, &job.layers, job.shared_layers)
+            .and_then(|skip_paths| load_missing_layers(instance_name, &cached, &skip_paths));
+        match result {
+            Ok(bytes) => {
+                outcome.skipped_layers = job.shared_layers;
+                outcome.skipped_bytes = bytes;
+                true
+            }
+            Err(e) => {
+                eprintln!(
+                    "Warning: layer-aware transfer failed for {} ({}), sending the whole image",
+                    job.images.join(", "),
+                    e
+                );
+                false
+            }
+        }
+    } else {
+        false
+    };
+    if !partial {
+        load_from_cache(instance_name, &cached)?;
+    }
+    if let Err(e) = cache::touch(&job.image_id, &job.images) {
+        eprintln!("Warning: failed to update cache index: {}", e);
+    }
+    drop(lock);
+
+    tag_in_vm(instance_name, job)?;
+    Ok(outcome)
+}
+
+/// レジストリ上の参照名（例: "ghcr.io/org/app:1.0" → "localhost:5050/ghcr.io/org/app:1.0"）
+///
+/// ダイジェスト指定の参照はダイジェストをタグにする（"redis@sha256:abc" → "redis:sha256-abc"）。
+fn registry_ref(image: &str, port: u16) -> String {
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+    let (repository, tag) = match name.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (name, "latest"),
+    };
+    let tag = match digest {
+        Some(digest) => digest.replace(':', "-"),
+        None => tag.to_string(),
+    };
+    // レジストリのポート指定（"host:5000/app"）はパスに使えないため置き換える
+    format!("localhost:{}/{}:{}", port, repository.replace(':', "-"), tag)
+}
+
+/// レジストリコンテナの状態
+#[derive(Debug, PartialEq, Eq)]
+struct RegistryState {
+    running: bool,
+    /// 5000/tcp を公開しているホスト側のポート
+    port: Option<u16>,
+}
+
+/// `docker inspect fracta-registry` の出力をパース
+fn parse_registry_state(output: &str) -> Option<RegistryState> {
+    let inspect: Value = serde_json::from_str(output).ok()?;
+    let container = inspect.get(0)?;
+    let running = container
+        .pointer("/State/Running")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let port = container
+        .pointer("/HostConfig/PortBindings/5000~1tcp/0/HostPort")
+        .and_then(|v| v.as_str())
+        .and_then(|port| port.parse().ok());
+    Some(RegistryState { running, port })
+}
+
+fn registry_state() -> Result<Option<RegistryState>> {
+    let output = Command::new("docker")
+        .args(["inspect", REGISTRY_CONTAINER])
+        .output()
+        .context("Failed to execute docker inspect")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(parse_registry_state(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// ホストにレジストリコンテナを起動（起動済みなら何もしない）
+///
+/// 別のポートで作られたコンテナは作り直す（データはボリュームに残る）。
+fn ensure_registry(port: u16) -> Result<()> {
+    let action = match registry_state()? {
+        Some(state) if state.port != Some(port) => {
+            println!(
+                "Recreating local registry ({}) on port {} (was {})...",
+                REGISTRY_CONTAINER,
+                port,
+                state
+                    .port
+                    .map(|p| p.to_string())
+                    .unwrap_or_else(|| "unpublished".to_string())
+            );
+            run_host_docker(&["rm", "-f", REGISTRY_CONTAINER])?;
+            "run"
+        }
+        Some(state) if state.running => return Ok(()),
+        Some(_) => "start",
+        None => "run",
+    };
+
+    let args: Vec<String> = if action == "start" {
+        vec!["start".to_string(), REGISTRY_CONTAINER.to_string()]
+    } else {
+        vec![
+            "run".to_string(),
+            "-d".to_string(),
+            "--name".to_string(),
+            REGISTRY_CONTAINER.to_string(),
+            "--restart".to_string(),
+            "unless-stopped".to_string(),
+            "-p".to_string(),
+            format!("127.0.0.1:{}:5000", port),
+            "-v".to_string(),
+            format!("{}:/var/lib/registry", REGISTRY_CONTAINER),
+            REGISTRY_IMAGE.to_string(),
+        ]
+    };
+
+    println!("Starting local registry ({}) on 127.0.0.1:{}...", REGISTRY_CONTAINER, port);
+    let output = Command::new("docker")
+        .args(&args)
+        .output()
+        .context(format!("Failed to execute docker {}", action))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Failed to start local registry: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// レジストリのボリュームがあるか
+fn registry_volume_exists() -> bool {
+    Command::new("docker")
+        .args(["volume", "inspect", REGISTRY_CONTAINER])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// `du -sk` の出力（2 回分）から GC 前後のサイズを取得（バイト）
+fn parse_gc_sizes(output: &str) -> Option<(u64, u64)> {
+    let mut sizes = output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next()?.parse::<u64>().ok())
+        .map(|kb| kb * 1024);
+    Some((sizes.next()?, sizes.next()?))
+}
+
+/// レジストリのタグが外れたイメージとそのレイヤーを削除（戻り値: GC 前後のサイズ、ボリュームがなければ None）
+///
+/// push と同時に実行すると使用中のレイヤーを消しうるため、コンテナを止めてから実行する。
+pub fn registry_gc() -> Result<Option<(u64, u64)>> {
+    if !registry_volume_exists() {
+        return Ok(None);
+    }
+    let was_running = registry_state()?.is_some_and(|state| state.running);
+    if was_running {
+        run_host_docker(&["stop", REGISTRY_CONTAINER])?;
+    }
+
+    let volume = format!("{}:/var/lib/registry", REGISTRY_CONTAINER);
+    let output = Command::new("docker")
+        .args(["run", "--rm", "-v", &volume, "--entrypoint", "sh", REGISTRY_IMAGE, "-c"])
+        .arg("set -e; du -sk /var/lib/registry; registry garbage-collect --delete-untagged /etc/docker/registry/config.yml >/dev/null; du -sk /var/lib/registry")
+        .output()
+        .context("Failed to execute registry garbage-collect");
+
+    if was_running {
+        run_host_docker(&["start", REGISTRY_CONTAINER])?;
+    }
+    let output = output?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("registry garbage-collect failed: {}", stderr.trim());
+    }
+    Ok(parse_gc_sizes(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// レジストリのコンテナとボリュームを削除（戻り値: ボリュームを削除したか）
+pub fn remove_registry() -> Result<bool> {
+    if registry_state()?.is_some() {
+        run_host_docker(&["rm", "-f", REGISTRY_CONTAINER])?;
+    }
+    if !registry_volume_exists() {
+        return Ok(false);
+    }
+    run_host_docker(&["volume", "rm", REGISTRY_CONTAINER])?;
+    Ok(true)
+}
+
+fn run_host_docker(args: &[&str]) -> Result<()> {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .context(format!("Failed to execute docker {}", args[0]))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("docker {} failed: {}", args.join(" "), stderr.trim());
+    }
+    Ok(())
+}
+
+/// ホストからレジストリに push し、VM から pull してタグを付け直す
+///
+/// 元のタグがないとレジストリの参照を外した時点でイメージが消えるため、先にタグを付ける。
+fn sync_image_via_registry(instance_name: &str, job: &SyncJob, port: u16) -> Result<SyncOutcome> {
+    let image = &job.images[0];
+    let reference = registry_ref(image, port);
+    run_host_docker(&["tag", image, &reference])?;
+    let pushed = run_host_docker(&["push", "-q", &reference]);
+    let _ = run_host_docker(&["rmi", &reference]);
+    pushed?;
+
+    // タグを付け直してからレジストリの参照を外す（ダイジェスト指定のみなら参照を残す）
+    let mut script = vec![format!("sudo docker pull -q '{}' >/dev/null", reference)];
+    let tags = tag_commands(&job.image_id, &job.images);
+    if !tags.is_empty() {
+        script.extend(tags);
+        script.push(format!("sudo docker rmi '{}' >/dev/null", reference));
+    }
+    let output = lima::shell(instance_name, &["bash", "-c", &script.join(" && ")])?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("docker pull/tag failed in VM: {}", stderr.trim());
+    }
+
+    Ok(SyncOutcome {
+        method: "registry",
+        skipped_layers: 0,
+        skipped_bytes: 0,
+    })
+}
+
+/// registry モードの同期（レジストリ起動 → ssh -R でトンネル → 並列に push / pull）
+fn sync_jobs_via_registry(
+    instance_name: &str,
+    jobs: Vec<SyncJob>,
+    options: &SyncOptions,
+) -> Result<Vec<String>> {
+    let port = options.registry_port;
+    ensure_registry(port)?;
+
+    let mut tunnel = ssh::start_reverse_forward(instance_name, port)
+        .context("Failed to expose the local registry to the VM")?;
+    let failures = run_sync_jobs(jobs, options.jobs, |job| {
+        sync_image_via_registry(instance_name, job, port)
+    });
+    let _ = tunnel.kill();
+    let _ = tunnel.wait();
+
+    Ok(failures)
+}
+
+/// 転送を並列に実行し、完了順に進捗を表示
+fn run_sync_jobs(
+    jobs: Vec<SyncJob>,
+    max_jobs: usize,
+    sync: impl Fn(&SyncJob) -> Result<SyncOutcome> + Sync,
+) -> Vec<String> {
+    let total = jobs.len();
+    let workers = max_jobs.clamp(1, total.max(1));
+    let queue = Mutex::new(jobs.into_iter().collect::<VecDeque<_>>());
+    let done = Mutex::new(0usize);
+    let failures = Mutex::new(Vec::new());
+
+    println!("Syncing {} image(s) with {} parallel job(s)...", total, workers);
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let job = match queue.lock().unwrap().pop_front() {
+                    Some(job) => job,
+                    None => break,
+                };
+                let started = Instant::now();
+                let result = sync(&job);
+
+                let mut done = done.lock().unwrap();
+                *done += 1;
+                let names = job.images.join(", ");
+                match result {
+                    Ok(outcome) => {
+                        let mut detail = vec![outcome.method.to_string()];
+                        if outcome.skipped_layers > 0 {
+                            detail.push(format!(
+                                "{}/{} layers already in VM, {} skipped",
+                                outcome.skipped_layers,
+                                job.layers.len(),
+                                utils::format_size(outcome.skipped_bytes)
+                            ));
+                        }
+                        detail.push(format!("{:.1}s", started.elapsed().as_secs_f64()));
+                        println!(
+                            "  [{}/{}] Synced {} ({})",
+                            *done,
+                            total,
+                            names,
+                            detail.join(", ")
+                        );
+                    }
+                    Err(e) => {
+                        println!("  [{}/{}] Failed {}: {}", *done, total, names, e);
+                        failures.lock().unwrap().push(names);
+                    }
+                }
+            });
+        }
+    });
+
+    failures.into_inner().unwrap()
+}
+
+/// ホストにないイメージを image_sync.missing に従って処理
+///
+/// 戻り値は同期の対象から外すイメージ（VM 内でビルド・pull されるもの）。
+fn resolve_missing(
+    instance_name: &str,
+    missing: &[&ComposeImage],
+    vm_ids: &HashMap<String, String>,
+    policy: MissingImagePolicy,
+    host_ids: &mut HashMap<String, String>,
+) -> Result<HashSet<String>> {
+    let mut handled = HashSet::new();
+    let mut targets = Vec::new();
+    for image in missing {
+        if image.build {
+            println!("Skipping (built in VM by compose): {}", image.image);
+            handled.insert(image.image.clone());
+        } else if !targets.contains(&image.image) {
+            targets.push(image.image.clone());
+        }
+    }
+    if targets.is_empty() {
+        return Ok(handled);
+    }
+
+    match policy {
+        MissingImagePolicy::Skip => {}
+        MissingImagePolicy::Fail => anyhow::bail!(
+            "Image(s) not found on host: {} (image_sync.missing = \"fail\")",
+            targets.join(", ")
+        ),
+        MissingImagePolicy::PullHost => {
+            for image in &targets {
+                println!("Pulling on host: {}", image);
+                let status = Command::new("docker")
+                    .args(["pull", image])
+                    .status()
+                    .context("Failed to execute docker pull")?;
+                if !status.success() {
+                    anyhow::bail!("docker pull failed on host for {}", image);
+                }
+            }
+            host_ids.extend(host_image_ids(&targets)?);
+        }
+        MissingImagePolicy::PullVm => {
+            for image in &targets {
+                handled.insert(image.clone());
+                if vm_ids.contains_key(image) {
+                    println!("Already in VM: {}", image);
+                    continue;
+                }
+                println!("Pulling in VM: {}", image);
+                let status = lima::shell_interactive(instance_name, &["sudo", "docker", "pull", image])?;
+                if !status.success() {
+                    anyhow::bail!("docker pull failed in VM for {}", image);
+                }
+            }
+        }
+    }
+    Ok(handled)
+}
+
+pub fn sync_images_to_vm(
+    instance_name: &str,
+    images: &[ComposeImage],
+    options: &SyncOptions,
+) -> Result<()> {
+    let (selected, excluded): (Vec<&ComposeImage>, Vec<&ComposeImage>) =
+        images.iter().partition(|image| options.selects(image));
+    for image in &excluded {
+        println!("Skipping (excluded by image_sync): {} ({})", image.image, image.service);
+    }
+
+    let mut names: Vec<String> = selected.iter().map(|image| image.image.clone()).collect();
+    names.sort();
+    names.dedup();
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let mut host_ids = host_image_ids(&names)?;
+    let vm_ids = vm_image_ids(instance_name, &names)?;
+    let missing: Vec<&ComposeImage> = selected
+        .iter()
+        .copied()
+        .filter(|image| !host_ids.contains_key(&image.image))
+        .collect();
+    let handled = resolve_missing(instance_name, &missing, &vm_ids, options.missing, &mut host_ids)?;
+    names.retain(|name| !handled.contains(name));
+
+    let (mut jobs, missing, synced) = plan_sync(&names, &host_ids, &vm_ids);
+
+    for image in &missing {
+        println!("Skipping (not found on host): {}", image);
+    }
+    for image in &synced {
+        println!("Already synced: {}", image);
+    }
+
+    let failures = match options.mode {
+        _ if jobs.is_empty() => Vec::new(),
+        // レジストリが重複排除するため、レイヤーの比較やキャッシュは不要
+        SyncMode::Registry => sync_jobs_via_registry(instance_name, jobs, options)?,
+        SyncMode::Load => {
+            // VM にすでにあるレイヤーは転送しない
+            let ids: Vec<&str> = jobs.iter().map(|job| job.image_id.as_str()).collect();
+            let mut host_layers = host_image_layers(&ids)?;
+            let vm = vm_layers(instance_name)?;
+            if vm.containerd_store {
+                println!("VM uses the containerd image store; sending whole images (layer-aware transfer needs the classic store).");
+            }
+            for job in &mut jobs {
+                job.layers = host_layers.remove(&job.image_id).unwrap_or_default();
+                if !vm.containerd_store {
+                    job.shared_layers = shared_layer_count(&job.layers, &vm.chains);
+                }
+            }
+
+            run_sync_jobs(jobs, options.jobs, |job| sync_image(instance_name, job))
+        }
+    };
+
+    // Cleanup stale cache entries
+    let used_keys: HashSet<String> = host_ids.values().map(|id| cache_key(id)).collect();
+    if let Err(e) = cache::enforce(&used_keys, options.cache_max_size) {
+        eprintln!("Warning: cache cleanup failed: {}", e);
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("Failed to sync image(s): {}", failures.join("; "));
+    }
+    Ok(())
+}
+
+/// インスタンス VM 間でイメージをコピー（from の docker save を to の docker load に直接流す）
+///
+/// ホストのキャッシュは経由しない。to に同じ ID のイメージがあるものは飛ばす。
+pub fn copy_between_vms(from: &str, to: &str, images: &[String], max_jobs: usize) -> Result<()> {
+    let from_ids = vm_image_ids(from, images)?;
+    let to_ids = vm_image_ids(to, images)?;
+    let (jobs, missing, synced) = plan_sync(images, &from_ids, &to_ids);
+
+    for image in &missing {
+        println!("Skipping (not found in {}): {}", from, image);
+    }
+    for image in &synced {
+        println!("Already up to date: {}", image);
+    }
+    if jobs.is_empty() {
+        println!("Nothing to copy.");
+        return Ok(());
+    }
+
+    let copied = Mutex::new(Vec::new());
+    let failures = run_sync_jobs(jobs, max_jobs, |job| {
+        let mut save = Command::new("limactl");
+        save.args(["shell", "--workdir", "/", from, "--", "sudo", "docker", "save"])
+            .args(&job.images);
+        pipe_to_docker_load(to, save, &format!("docker save in {}", from))?;
+        tag_in_vm(to, job)?;
+        copied.lock().unwrap().extend(job.images.iter().cloned());
+        Ok(SyncOutcome {
+            method: "streamed",
+            skipped_layers: 0,
+            skipped_bytes: 0,
+        })
+    });
+
+    let mut copied = copied.into_inner().unwrap();
+    copied.sort();
+    if !copied.is_empty() {
+        println!("\nCopied {} image(s) from {} to {}:", copied.len(), from, to);
+        for image in &copied {
+            println!("  {}", image);
+        }
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("Failed to copy image(s): {}", failures.join("; "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(image, id)| (image.to_string(), id.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_image_ids() {
+        let output = "postgres:16\tsha256:aaa\nmissing:latest\t\napp-web\tsha256:bbb\n";
+        let parsed = parse_image_ids(output);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed["postgres:16"], "sha256:aaa");
+        assert!(!parsed.contains_key("missing:latest"));
+    }
+
+    #[test]
+    fn test_image_ids_script() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // 引数の最後（イメージ名）で振る舞いを変える偽の docker
+        let dir = tempfile::tempdir().unwrap();
+        let docker = dir.path().join("docker");
+        std::fs::write(
+            &docker,
+            r#"#!/bin/sh
+for last; do :; done
+case "$last" in
+  present) echo sha256:aaa ;;
+  missing) echo "Error response from daemon: No such image: missing:latest" >&2; exit 1 ;;
+  *) echo "Cannot connect to the Docker daemon" >&2; exit 1 ;;
+esac
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let run = |images: &[&str]| {
+            let output = Command::new("bash")
+                .args(["-c", &image_ids_script(&docker.to_string_lossy()), "fracta"])
+                .args(images)
+                .output()
+                .unwrap();
+            image_ids_from_output(&output, "host")
+        };
+
+        let ids = run(&["present", "missing"]).unwrap();
+        assert_eq!(ids.len(), 1);
+        assert_eq!(ids["present"], "sha256:aaa");
+
+        let err = run(&["present", "broken"]).unwrap_err();
+        assert!(err.to_string().contains("Cannot connect to the Docker daemon"));
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        let images: Vec<String> = ["app-web", "app-worker", "postgres:16", "redis:7", "missing"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let host = ids(&[
+            ("app-web", "sha256:app"),
+            ("app-worker", "sha256:app"),
+            ("postgres:16", "sha256:pg"),
+            ("redis:7", "sha256:redis"),
+        ]);
+        let vm = ids(&[("postgres:16", "sha256:pg"), ("redis:7", "sha256:old")]);
+
+        let (jobs, missing, synced) = plan_sync(&images, &host, &vm);
+        assert_eq!(missing, vec!["missing"]);
+        assert_eq!(synced, vec!["postgres:16"]);
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].images, vec!["app-web", "app-worker"]);
+        assert_eq!(jobs[1].image_id, "sha256:redis");
+    }
+
+    #[test]
+    fn test_image_selection() {
+        let config: Config = toml::from_str(
+            "[image_sync]\ninclude = [\"app-*\", \"db\"]\nexclude = [\"*-worker\"]\nmissing = \"pull-vm\"\n",
+        )
+        .unwrap();
+        let options = SyncOptions::from_config(&config).unwrap();
+        assert_eq!(options.missing, MissingImagePolicy::PullVm);
+
+        let image = |service: &str, image: &str| ComposeImage {
+            service: service.to_string(),
+            image: image.to_string(),
+            build: false,
+        };
+        assert!(options.selects(&image("web", "app-web")));
+        assert!(options.selects(&image("db", "postgres:16")));
+        assert!(!options.selects(&image("worker", "app-worker")));
+        assert!(!options.selects(&image("cache", "redis:7")));
+
+        let defaults = SyncOptions::from_config(&Config::default()).unwrap();
+        assert_eq!(defaults.missing, MissingImagePolicy::Skip);
+        assert!(defaults.selects(&image("cache", "redis:7")));
+        assert!(MissingImagePolicy::parse("download").is_err());
+    }
+
+    #[test]
+    fn test_registry_ref() {
+        assert_eq!(registry_ref("app-web", 5050), "localhost:5050/app-web:latest");
+        assert_eq!(registry_ref("postgres:16", 5050), "localhost:5050/postgres:16");
+        assert_eq!(
+            registry_ref("ghcr.io/org/app:1.0", 5000),
+            "localhost:5000/ghcr.io/org/app:1.0"
+        );
+        assert_eq!(
+            registry_ref("registry.local:5000/team/api", 5050),
+            "localhost:5050/registry.local-5000/team/api:latest"
+        );
+        assert_eq!(
+            registry_ref("redis:7@sha256:abc", 5050),
+            "localhost:5050/redis:sha256-abc"
+        );
+        assert_eq!(
+            registry_ref("ghcr.io/org/app@sha256:def", 5050),
+            "localhost:5050/ghcr.io/org/app:sha256-def"
+        );
+
+        // ダイジェスト指定の参照は VM 内でタグを付け直さない
+        let images = vec!["redis:7".to_string(), "redis:7@sha256:abc".to_string()];
+        assert_eq!(tag_commands("sha256:id", &images), vec!["sudo docker tag sha256:id 'redis:7'"]);
+        assert!(tag_commands("sha256:id", &images[1..]).is_empty());
+        assert!(SyncMode::parse("Registry").unwrap() == SyncMode::Registry);
+        assert!(SyncMode::parse("rsync").is_err());
+    }
+
+    #[test]
+    fn test_shared_layer_count() {
+        let vm_chains = parse_layer_lines(
+            "[\"sha256:base\",\"sha256:deps\",\"sha256:app-old\"]\n[\"sha256:base\"]\n[]\nnot json\n",
+        );
+        assert_eq!(vm_chains.len(), 2);
+
+        let layers: Vec<String> = ["sha256:base", "sha256:deps", "sha256:app-new"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(shared_layer_count(&layers, &vm_chains), 2);
+        assert_eq!(shared_layer_count(&layers[1..], &vm_chains), 0);
+        assert_eq!(shared_layer_count(&layers, &[]), 0);
+    }
+
+    #[test]
+    fn test_shared_layer_paths() {
+        let paths: Vec<String> = ["blobs/sha256/base", "blobs/sha256/deps", "blobs/sha256/app"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let layers: Vec<String> = ["sha256:base", "sha256:deps", "sha256:app"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let skip = shared_layer_paths(&paths, &layers, 2).unwrap();
+        assert_eq!(skip.len(), 2);
+        assert!(skip.contains("blobs/sha256/base"));
+        assert!(skip.contains("blobs/sha256/deps"));
+
+        // 後続のレイヤーと同じ内容（同じパス）は残す
+        let repeated = vec![paths[0].clone(), paths[1].clone(), paths[0].clone()];
+        let skip = shared_layer_paths(&repeated, &layers, 2).unwrap();
+        assert_eq!(skip.into_iter().collect::<Vec<_>>(), vec!["blobs/sha256/deps"]);
+
+        assert!(shared_layer_paths(&paths, &layers[..2], 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_registry_state() {
+        let output = r#"[{"State":{"Running":true},"HostConfig":{"PortBindings":{"5000/tcp":[{"HostIp":"127.0.0.1","HostPort":"5050"}]}}}]"#;
+        assert_eq!(
+            parse_registry_state(output),
+            Some(RegistryState { running: true, port: Some(5050) })
+        );
+
+        let output = r#"[{"State":{"Running":false},"HostConfig":{"PortBindings":{}}}]"#;
+        assert_eq!(
+            parse_registry_state(output),
+            Some(RegistryState { running: false, port: None })
+        );
+        assert_eq!(parse_registry_state("[]"), None);
+    }
+
+    #[test]
+    fn test_parse_gc_sizes() {
+        let output = "2048\t/var/lib/registry\n512\t/var/lib/registry\n";
+        assert_eq!(parse_gc_sizes(output), Some((2048 * 1024, 512 * 1024)));
+        assert_eq!(parse_gc_sizes("2048\t/var/lib/registry\n"), None);
+    }
+
+    #[test]
+    fn test_parse_vm_layers() {
+        let classic = "driver-status [[\"Backing Filesystem\",\"extfs\"],[\"Supports d_type\",\"true\"]]\n[\"sha256:a\",\"sha256:b\"]\n";
+        let layers = parse_vm_layers(classic);
+        assert!(!layers.containerd_store);
+        assert_eq!(layers.chains, vec![vec!["sha256:a".to_string(), "sha256:b".to_string()]]);
+
+        let containerd = "driver-status [[\"driver-type\",\"io.containerd.snapshotter.v1\"]]\n";
+        let layers = parse_vm_layers(containerd);
+        assert!(layers.containerd_store);
+        assert!(layers.chains.is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::{Context, Result};
+use std::io::{self, Read, Write};
+
+const BLOCK: usize = 512;
+
+/// copy の結果
+#[derive(Debug, Default)]
+pub struct Copied {
+    /// 書き出さなかったエントリのデータのバイト数
+    pub skipped_bytes: u64,
+    /// capture で指定したエントリの内容
+    pub captured: Option<Vec<u8>>,
+}
+
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK as u64) * BLOCK as u64
+}
+
+/// NUL 終端のフィールド
+fn field(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+/// サイズフィールド（8 進数、先頭ビットが立っていれば base-256）
+fn parse_size(bytes: &[u8]) -> Result<u64> {
+    if bytes[0] & 0x80 != 0 {
+        let mut size: u64 = u64::from(bytes[0] & 0x7f);
+        for b in &bytes[1..] {
+            size = size
+                .checked_mul(256)
+                .and_then(|s| s.checked_add(u64::from(*b)))
+                .context("tar entry size overflows")?;
+        }
+        return Ok(size);
+    }
+    let text = String::from_utf8_lossy(field(bytes));
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).context(format!("Invalid tar entry size '{}'", text))
+}
+
+/// ustar ヘッダのパス（prefix + name）
+fn header_path(header: &[u8]) -> String {
+    let name = String::from_utf8_lossy(field(&header[0..100])).to_string();
+    let prefix = if &header[257..262] == b"ustar" {
+        String::from_utf8_lossy(field(&header[345..500])).to_string()
+    } else {
+        String::new()
+    };
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// PAX 拡張ヘッダ（"<len> key=value\n" の繰り返し）から path と size を取り出す
+fn parse_pax(data: &[u8]) -> (Option<String>, Option<u64>) {
+    let mut path = None;
+    let mut size = None;
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|b| *b == b' ') {
+        let len: usize = match std::str::from_utf8(&rest[..space]).ok().and_then(|l| l.parse().ok()) {
+            Some(len) if len > space && len <= rest.len() => len,
+            _ => break,
+        };
+        let record = String::from_utf8_lossy(&rest[space + 1..len]);
+        let record = record.trim_end_matches('\n');
+        if let Some((key, value)) = record.split_once('=') {
+            match key {
+                "path" => path = Some(value.to_string()),
+                "size" => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+        rest = &rest[len..];
+    }
+    (path, size)
+}
+
+/// manifest.json のパスと比較できる形に揃える
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./")
+}
+
+/// tar ストリームを展開せずに 1 エントリずつ writer にコピーする
+///
+/// 扱うのは ustar ヘッダと、長いパス用の PAX (`x`) / GNU (`L`) 拡張ヘッダのみ。
+/// ヘッダとデータはそのまま書き出すため、チェックサムは再計算しない。
+/// keep が false を返したエントリ（拡張ヘッダを含む）は書き出さずに読み飛ばす。
+/// capture と同じパスのエントリは内容を戻り値に含める。
+pub fn copy(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    keep: impl Fn(&str) -> bool,
+    capture: Option<&str>,
+) -> Result<Copied> {
+    let mut copied = Copied::default();
+    // 次のエントリに付く拡張ヘッダ（ヘッダとデータ）と、そこで指定されたパス・サイズ
+    let mut pending: Vec<u8> = Vec::new();
+    let mut pending_path: Option<String> = None;
+    let mut pending_size: Option<u64> = None;
+    let mut header = [0u8; BLOCK];
+
+    loop {
+        reader
+            .read_exact(&mut header)
+            .context("Unexpected end of tar archive")?;
+        if header.iter().all(|b| *b == 0) {
+            // 終端ブロック
+            writer.write_all(&[0u8; BLOCK * 2])?;
+            writer.flush()?;
+            io::copy(&mut reader, &mut io::sink()).context("Failed to read tar archive")?;
+            return Ok(copied);
+        }
+
+        let typeflag = header[156];
+        let header_size = parse_size(&header[124..136])?;
+
+        if typeflag == b'x' || typeflag == b'L' {
+            let mut data = vec![0u8; padded(header_size) as usize];
+            reader
+                .read_exact(&mut data)
+                .context("Unexpected end of tar archive")?;
+            let body = &data[..header_size as usize];
+            if typeflag == b'x' {
+                let (path, size) = parse_pax(body);
+                pending_path = path.or(pending_path);
+                pending_size = size.or(pending_size);
+            } else {
+                pending_path = Some(String::from_utf8_lossy(field(body)).to_string());
+            }
+            pending.extend_from_slice(&header);
+            pending.extend_from_slice(&data);
+            continue;
+        }
+
+        let path = pending_path.take().unwrap_or_else(|| header_path(&header));
+        let path = normalize(&path);
+        let size = pending_size.take().unwrap_or(header_size);
+        let mut data = (&mut reader).take(padded(size));
+
+        if !keep(path) {
+            pending.clear();
+            io::copy(&mut data, &mut io::sink()).context("Failed to read tar archive")?;
+            copied.skipped_bytes += size;
+            continue;
+        }
+
+        writer.write_all(&pending)?;
+        pending.clear();
+        writer.write_all(&header)?;
+        if capture == Some(path) {
+            let mut content = Vec::new();
+            data.read_to_end(&mut content)
+                .context("Failed to read tar archive")?;
+            writer.write_all(&content)?;
+            content.truncate(size as usize);
+            copied.captured = Some(content);
+        } else {
+            io::copy(&mut data, &mut writer).context("Failed to copy tar entry")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str, size: usize, typeflag: u8) -> Vec<u8> {
+        let mut header = vec![0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header
+    }
+
+    fn entry(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = header(name, data.len(), b'0');
+        bytes.extend_from_slice(data);
+        bytes.resize(BLOCK + padded(data.len() as u64) as usize, 0);
+        bytes
+    }
+
+    fn pax_entry(path: &str, data: &[u8]) -> Vec<u8> {
+        let record = format!("path={}\n", path);
+        // 長さは自身の桁数を含む
+        let len = record.len() + 1 + (record.len() + 4).to_string().len();
+        let pax = format!("{} {}", len, record);
+        let mut bytes = header("PaxHeaders/x", pax.len(), b'x');
+        bytes.extend_from_slice(pax.as_bytes());
+        bytes.resize(BLOCK + padded(pax.len() as u64) as usize, 0);
+        bytes.extend(entry("truncated-name", data));
+        bytes
+    }
+
+    fn archive(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = entries.concat();
+        bytes.extend_from_slice(&[0u8; BLOCK * 2]);
+        bytes
+    }
+
+    /// コピー結果のエントリ名を読み直す
+    fn names(archive: &[u8]) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut offset = 0;
+        while offset + BLOCK <= archive.len() {
+            let header = &archive[offset..offset + BLOCK];
+            if header.iter().all(|b| *b == 0) {
+                break;
+            }
+            names.push(header_path(header));
+            offset += BLOCK + padded(parse_size(&header[124..136]).unwrap()) as usize;
+        }
+        names
+    }
+
+    #[test]
+    fn test_copy_skips_entries() {
+        let input = archive(&[
+            entry("blobs/sha256/aaa", &[1u8; 700]),
+            entry("blobs/sha256/bbb", b"layer b"),
+            entry("manifest.json", br#"[{"Layers":["blobs/sha256/aaa"]}]"#),
+        ]);
+        let mut output = Vec::new();
+        let copied = copy(
+            &input[..],
+            &mut output,
+            |path| path != "blobs/sha256/aaa",
+            Some("manifest.json"),
+        )
+        .unwrap();
+
+        assert_eq!(copied.skipped_bytes, 700);
+        assert_eq!(
+            copied.captured.as_deref(),
+            Some(&br#"[{"Layers":["blobs/sha256/aaa"]}]"#[..])
+        );
+        assert_eq!(names(&output), vec!["blobs/sha256/bbb", "manifest.json"]);
+        assert_eq!(output.len(), input.len() - BLOCK * 3);
+    }
+
+    #[test]
+    fn test_copy_uses_pax_path() {
+        let long = format!("{}/layer.tar", "a".repeat(120));
+        let input = archive(&[pax_entry(&long, b"data"), entry("repositories", b"{}")]);
+
+        let mut output = Vec::new();
+        let copied = copy(&input[..], &mut output, |path| path != long, None).unwrap();
+        assert_eq!(copied.skipped_bytes, 4);
+        assert_eq!(names(&output), vec!["repositories"]);
+
+        let mut output = Vec::new();
+        copy(&input[..], &mut output, |_| true, None).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size(b"00000001750\0").unwrap(), 1000);
+        let mut base256 = [0u8; 12];
+        base256[0] = 0x80;
+        base256[11] = 0x02;
+        base256[10] = 0x01;
+        assert_eq!(parse_size(&base256).unwrap(), 258);
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::{Context, Result};
 use std::path::Path;
 use std::process::{Command, Stdio};
 
+use super::logs;
 use crate::utils;
 
 /// Lima インスタンス名を生成
@@ -10,6 +11,16 @@ pub fn instance_name(worktree_name: &str) -> String {
     format!("fracta-{}", sanitized)
 }
 
+/// VM 間ネットワークで使う安定したホスト名を生成（例: feature-a.fracta）
+pub fn hostname(worktree_name: &str) -> String {
+    let label: String = utils::sanitize_name(worktree_name)
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("{}.fracta", label.trim_matches('-'))
+}
+
 /// Lima インスタンスの状態
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InstanceStatus {
@@ -28,6 +39,17 @@ impl std::fmt::Display for InstanceStatus {
     }
 }
 
+/// limactl list で得られるインスタンス情報
+#[derive(Debug, Clone)]
+pub struct InstanceInfo {
+    pub name: String,
+    pub status: InstanceStatus,
+    /// メモリ（バイト）
+    pub memory: u64,
+    /// ディスク（バイト）
+    pub disk: u64,
+}
+
 /// Lima インスタンスを作成
 pub fn create(template_path: &Path, instance_name: &str) -> Result<()> {
     let output = Command::new("limactl")
@@ -48,9 +70,19 @@ pub fn create(template_path: &Path, instance_name: &str) -> Result<()> {
     Ok(())
 }
 
-/// Lima インスタンスを起動
-pub fn start(instance_name: &str) -> Result<()> {
-    start_with_timeout(instance_name, None)
+/// テンプレートを limactl validate で検証
+pub fn validate(template_path: &Path) -> Result<()> {
+    let output = Command::new("limactl")
+        .args(["validate", template_path.to_string_lossy().as_ref()])
+        .output()
+        .context("Failed to execute limactl validate")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("limactl validate failed: {}", stderr.trim());
+    }
+
+    Ok(())
 }
 
 /// Lima インスタンスを起動（タイムアウト指定）
@@ -70,7 +102,11 @@ pub fn start_with_timeout(instance_name: &str, timeout: Option<&str>) -> Result<
         .context("Failed to execute limactl start")?;
 
     if !output.status.success() {
-        anyhow::bail!("limactl start failed for {}", instance_name);
+        logs::print_failure_summary(instance_name);
+        anyhow::bail!(
+            "limactl start failed for {} (run 'fracta vm logs' or 'fracta vm logs --provision' for details)",
+            instance_name
+        );
     }
 
     Ok(())
@@ -152,6 +188,21 @@ pub fn info(instance_name: &str) -> Result<InstanceStatus> {
     Ok(status)
 }
 
+/// 全 Lima インスタンスの情報を取得
+pub fn list() -> Result<Vec<InstanceInfo>> {
+    let output = Command::new("limactl")
+        .args(["list", "--json"])
+        .output()
+        .context("Failed to execute limactl list")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("limactl list failed: {}", stderr.trim());
+    }
+
+    Ok(parse_list_json(&String::from_utf8_lossy(&output.stdout)))
+}
+
 /// Lima がインストールされているか確認
 pub fn is_available() -> bool {
     Command::new("limactl")
@@ -161,13 +212,45 @@ pub fn is_available() -> bool {
         .unwrap_or(false)
 }
 
+/// Lima インスタンスのディレクトリ（~/.lima/<instance>）を取得
+pub fn instance_dir(instance_name: &str) -> std::path::PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    std::path::PathBuf::from(home).join(".lima").join(instance_name)
+}
+
+/// VM の起動時刻（ホストエージェントの ha.pid 作成時刻、UNIX 秒）
+pub fn started_at(instance_name: &str) -> Option<u64> {
+    let modified = std::fs::metadata(instance_dir(instance_name).join("ha.pid"))
+        .and_then(|m| m.modified())
+        .ok()?;
+    modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
 /// SSH 設定ファイルのパスを取得
 pub fn ssh_config_path(instance_name: &str) -> std::path::PathBuf {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    std::path::PathBuf::from(home)
-        .join(".lima")
-        .join(instance_name)
-        .join("ssh.config")
+    instance_dir(instance_name).join("ssh.config")
+}
+
+fn parse_list_json(json: &str) -> Vec<InstanceInfo> {
+    json.lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line.trim()).ok())
+        .filter_map(|value| {
+            let name = value.get("name")?.as_str()?.to_string();
+            let status = match value.get("status").and_then(|v| v.as_str()) {
+                Some("Running") => InstanceStatus::Running,
+                _ => InstanceStatus::Stopped,
+            };
+            Some(InstanceInfo {
+                name,
+                status,
+                memory: value.get("memory").and_then(|v| v.as_u64()).unwrap_or(0),
+                disk: value.get("disk").and_then(|v| v.as_u64()).unwrap_or(0),
+            })
+        })
+        .collect()
 }
 
 fn parse_status_from_json(json: &str, instance_name: &str) -> InstanceStatus {
@@ -205,6 +288,27 @@ mod tests {
         assert_eq!(instance_name("feature/new"), "fracta-feature-new");
     }
 
+    #[test]
+    fn test_hostname() {
+        assert_eq!(hostname("develop"), "develop.fracta");
+        assert_eq!(hostname("feature/New_UI"), "feature-new-ui.fracta");
+    }
+
+    #[test]
+    fn test_parse_list_json() {
+        let json = concat!(
+            r#"{"name": "fracta-a", "status": "Running", "cpus": 4, "memory": 8589934592, "disk": 53687091200}"#,
+            "\n",
+            r#"{"name": "fracta-b", "status": "Stopped"}"#,
+        );
+        let list = parse_list_json(json);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].status, InstanceStatus::Running);
+        assert_eq!(list[0].memory, 8589934592);
+        assert_eq!(list[1].status, InstanceStatus::Stopped);
+        assert_eq!(list[1].disk, 0);
+    }
+
     #[test]
     fn test_parse_status_from_json() {
         // NDJSON 形式（改行区切り）
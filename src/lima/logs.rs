@@ -0,0 +1,271 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use super::client;
+use super::template::PROBE_FAILED_MARKER;
+
+/// ホストエージェントのログ（limactl start の詳細）
+const HA_STDERR_LOG: &str = "ha.stderr.log";
+
+/// シリアルコンソールのログ（qemu: serial.log, vz: serialv.log）
+const SERIAL_LOGS: &[&str] = &["serial.log", "serialv.log"];
+
+/// ゲスト内の cloud-init / provision スクリプトの出力
+pub const GUEST_PROVISION_LOG: &str = "/var/log/cloud-init-output.log";
+
+/// 失敗時に表示する行数
+const SUMMARY_LINES: usize = 20;
+
+pub fn ha_log_path(instance_name: &str) -> PathBuf {
+    client::instance_dir(instance_name).join(HA_STDERR_LOG)
+}
+
+/// 存在するシリアルログのパスを取得
+pub fn serial_log_path(instance_name: &str) -> Option<PathBuf> {
+    let dir = client::instance_dir(instance_name);
+    SERIAL_LOGS
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+}
+
+fn last_lines(content: &str, count: usize) -> Vec<&str> {
+    let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+    let start = lines.len().saturating_sub(count);
+    lines[start..].to_vec()
+}
+
+/// ha.stderr.log の JSON 行を "time level msg" 形式に整形
+pub fn format_ha_line(line: &str) -> String {
+    let value = match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(value) => value,
+        Err(_) => return line.to_string(),
+    };
+    let field = |key: &str| value.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    let mut formatted = format!("{} {:<7} {}", field("time"), field("level"), field("msg"));
+    if let Some(error) = value.get("error").and_then(|v| v.as_str()) {
+        formatted.push_str(&format!(" (error: {})", error));
+    }
+    formatted.trim().to_string()
+}
+
+/// 失敗原因の調査に関係する ha.stderr.log の行か
+fn is_relevant_ha_line(line: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(value) => {
+            let level = value.get("level").and_then(|v| v.as_str()).unwrap_or_default();
+            let msg = value.get("msg").and_then(|v| v.as_str()).unwrap_or_default();
+            matches!(level, "warning" | "error" | "fatal")
+                || msg.contains("requirement")
+                || msg.contains("provision")
+        }
+        Err(_) => !line.trim().is_empty(),
+    }
+}
+
+/// ha.stderr.log から失敗調査向けの行を抜き出す
+pub fn relevant_ha_lines(content: &str, count: usize) -> Vec<String> {
+    let lines: Vec<&str> = content.lines().filter(|l| is_relevant_ha_line(l)).collect();
+    let start = lines.len().saturating_sub(count);
+    lines[start..].iter().map(|l| format_ha_line(l)).collect()
+}
+
+/// ha.stderr.log から失敗した vm_probes の hint を取り出す
+pub fn probe_failures(content: &str) -> Vec<String> {
+    let mut hints: Vec<String> = Vec::new();
+    for line in content.lines() {
+        // JSON 行ならエスケープを戻してから探す
+        let text = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(value) => value
+                .as_object()
+                .map(|obj| {
+                    obj.values()
+                        .filter_map(|v| v.as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .unwrap_or_default(),
+            Err(_) => line.to_string(),
+        };
+        for part in text.split(PROBE_FAILED_MARKER).skip(1) {
+            // Lima はスクリプトの出力を Go の %q 形式で埋め込むため、"\n" の手前までを hint とする
+            let line = part.lines().next().unwrap_or_default();
+            let hint = line.split("\\n").next().unwrap_or_default().trim().trim_end_matches('"');
+            if !hint.is_empty() && !hints.iter().any(|h| h == hint) {
+                hints.push(hint.to_string());
+            }
+        }
+    }
+    hints
+}
+
+/// ゲスト内の provision ログ末尾を取得（VM に接続できなければ None）
+fn guest_provision_tail(instance_name: &str, count: usize) -> Option<String> {
+    let output = client::shell(
+        instance_name,
+        &["sudo", "tail", "-n", &count.to_string(), GUEST_PROVISION_LOG],
+    )
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if stdout.trim().is_empty() {
+        None
+    } else {
+        Some(stdout)
+    }
+}
+
+/// limactl start 失敗時に各ログの末尾を表示
+pub fn print_failure_summary(instance_name: &str) {
+    eprintln!("\n=== limactl start failed: {} ===", instance_name);
+
+    if let Ok(content) = std::fs::read_to_string(ha_log_path(instance_name)) {
+        let hints = probe_failures(&content);
+        if !hints.is_empty() {
+            eprintln!("\n--- Failed probes (vm_probes) ---");
+            for hint in hints {
+                eprintln!("Hint: {}", hint);
+            }
+        }
+
+        let lines = relevant_ha_lines(&content, SUMMARY_LINES);
+        if !lines.is_empty() {
+            eprintln!("\n--- {} (warnings/errors) ---", HA_STDERR_LOG);
+            for line in lines {
+                eprintln!("{}", line);
+            }
+        }
+    }
+
+    if let Some(path) = serial_log_path(instance_name) {
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            eprintln!(
+                "\n--- {} (last {} lines) ---",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                SUMMARY_LINES
+            );
+            for line in last_lines(&content, SUMMARY_LINES) {
+                eprintln!("{}", line);
+            }
+        }
+    }
+
+    if let Some(tail) = guest_provision_tail(instance_name, SUMMARY_LINES) {
+        eprintln!("\n--- {} (last {} lines) ---", GUEST_PROVISION_LOG, SUMMARY_LINES);
+        eprint!("{}", tail);
+    }
+    eprintln!();
+}
+
+/// ホスト側のログ（ha.stderr.log / serial log）を表示
+pub fn show_host_logs(instance_name: &str, lines: usize, follow: bool) -> Result<()> {
+    let mut paths = Vec::new();
+    let ha_log = ha_log_path(instance_name);
+    if ha_log.exists() {
+        paths.push(ha_log);
+    }
+    if let Some(serial) = serial_log_path(instance_name) {
+        paths.push(serial);
+    }
+    if paths.is_empty() {
+        anyhow::bail!(
+            "No logs found in {}. Has the VM been started?",
+            client::instance_dir(instance_name).display()
+        );
+    }
+
+    if follow {
+        let status = Command::new("tail")
+            .args(["-n", &lines.to_string(), "-F"])
+            .args(&paths)
+            .stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .context("Failed to execute tail")?;
+        if !status.success() {
+            anyhow::bail!("tail exited with error");
+        }
+        return Ok(());
+    }
+
+    for path in &paths {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read {}", path.display()))?;
+        println!("==> {} <==", path.display());
+        let is_ha_log = path.ends_with(HA_STDERR_LOG);
+        for line in last_lines(&content, lines) {
+            if is_ha_log {
+                println!("{}", format_ha_line(line));
+            } else {
+                println!("{}", line);
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// ゲスト内の provision ログを表示
+pub fn show_provision_log(instance_name: &str, lines: usize, follow: bool) -> Result<()> {
+    let count = lines.to_string();
+    let mut command = vec!["sudo", "tail", "-n", &count];
+    if follow {
+        command.push("-F");
+    }
+    command.push(GUEST_PROVISION_LOG);
+
+    let status = client::shell_interactive(instance_name, &command)?;
+    if !status.success() {
+        anyhow::bail!("Failed to read {} in VM", GUEST_PROVISION_LOG);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_ha_line() {
+        let line = r#"{"level":"info","msg":"SSH Local Port: 60022","time":"2026-01-01T00:00:00Z"}"#;
+        assert_eq!(
+            format_ha_line(line),
+            "2026-01-01T00:00:00Z info    SSH Local Port: 60022"
+        );
+        assert_eq!(format_ha_line("plain text"), "plain text");
+    }
+
+    #[test]
+    fn test_probe_failures() {
+        let content = [
+            r#"{"level":"info","msg":"Waiting for the final requirement 2 of 2","time":"t1"}"#,
+            r#"{"level":"error","msg":"final requirement failed","error":"stdout=\"FRACTA_PROBE_FAILED: Docker daemon is not answering\\n\", exit status 1","time":"t2"}"#,
+            r#"{"level":"error","msg":"final requirement failed","error":"stdout=\"FRACTA_PROBE_FAILED: Docker daemon is not answering\\n\", exit status 1","time":"t3"}"#,
+            "FRACTA_PROBE_FAILED: cache mount missing",
+        ]
+        .join("\n");
+        assert_eq!(
+            probe_failures(&content),
+            vec!["Docker daemon is not answering", "cache mount missing"]
+        );
+        assert!(probe_failures("").is_empty());
+    }
+
+    #[test]
+    fn test_relevant_ha_lines() {
+        let content = [
+            r#"{"level":"info","msg":"Starting QEMU","time":"t1"}"#,
+            r#"{"level":"info","msg":"Waiting for the final requirement 1 of 1","time":"t2"}"#,
+            r#"{"level":"error","msg":"probe failed","error":"exit 1","time":"t3"}"#,
+        ]
+        .join("\n");
+        let lines = relevant_ha_lines(&content, 10);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("final requirement"));
+        assert!(lines[1].ends_with("probe failed (error: exit 1)"));
+    }
+}
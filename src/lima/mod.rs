@@ -1,3 +1,5 @@
 pub mod client;
+pub mod logs;
+pub mod placeholder;
 pub mod ssh;
 pub mod template;
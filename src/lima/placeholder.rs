@@ -0,0 +1,149 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// カスタムテンプレートのプレースホルダ展開
+///
+/// 書式:
+///   {{NAME}}            既知の変数（未知の名前はエラー）
+///   {{NAME:-default}}   値が空ならデフォルト値
+///   {{ENV:VAR}}         ホストの環境変数（未設定でデフォルトもなければエラー）
+///   {{ENV:VAR:-default}}
+///
+/// 大文字で始まらない `{{...}}`（Lima の `{{.User}}` など）はそのまま残す。
+pub fn render(template: &str, vars: &BTreeMap<&str, String>) -> Result<String> {
+    render_with(template, vars, |name| std::env::var(name).ok())
+}
+
+fn render_with(
+    template: &str,
+    vars: &BTreeMap<&str, String>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut errors = Vec::new();
+
+    for (line_no, line) in template.split_inclusive('\n').enumerate() {
+        let mut rest = line;
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = match after.find("}}") {
+                Some(end) => end,
+                None => {
+                    rest = &rest[start..];
+                    break;
+                }
+            };
+            let inner = &after[..end];
+            match expand(inner.trim(), vars, &env) {
+                Some(Ok(value)) => output.push_str(&value),
+                Some(Err(e)) => {
+                    errors.push(format!("line {}: {}", line_no + 1, e));
+                }
+                None => {
+                    output.push_str("{{");
+                    output.push_str(inner);
+                    output.push_str("}}");
+                }
+            }
+            rest = &after[end + 2..];
+        }
+        output.push_str(rest);
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Failed to expand template placeholders:\n  {}\nKnown placeholders: {}, ENV:<VAR>",
+            errors.join("\n  "),
+            vars.keys().copied().collect::<Vec<_>>().join(", ")
+        );
+    }
+    Ok(output)
+}
+
+/// プレースホルダを展開（fracta の書式でなければ None）
+fn expand(
+    inner: &str,
+    vars: &BTreeMap<&str, String>,
+    env: &impl Fn(&str) -> Option<String>,
+) -> Option<Result<String, String>> {
+    if !inner.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return None;
+    }
+
+    let (name, default) = match inner.split_once(":-") {
+        Some((name, default)) => (name.trim(), Some(default)),
+        None => (inner, None),
+    };
+    let is_valid_name = |s: &str| {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+    };
+
+    let value = if let Some(var) = name.strip_prefix("ENV:") {
+        if var.is_empty() || !var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Some(Err(format!("invalid environment variable name in {{{{{}}}}}", inner)));
+        }
+        env(var)
+    } else if is_valid_name(name) {
+        match vars.get(name) {
+            Some(value) => Some(value.clone()),
+            None => return Some(Err(format!("unknown placeholder {{{{{}}}}}", name))),
+        }
+    } else {
+        return None;
+    };
+
+    match (value.filter(|v| !v.is_empty()), default) {
+        (Some(value), _) => Some(Ok(value)),
+        (None, Some(default)) => Some(Ok(default.to_string())),
+        (None, None) if name.starts_with("ENV:") => Some(Err(format!(
+            "environment variable {} is not set (use {{{{{}:-default}}}})",
+            &name[4..],
+            name
+        ))),
+        (None, None) => Some(Ok(String::new())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> BTreeMap<&'static str, String> {
+        BTreeMap::from([
+            ("WORKTREE_PATH", "/repo-feature".to_string()),
+            ("BRANCH", "feature/a".to_string()),
+            ("INSTANCE_NAME", String::new()),
+        ])
+    }
+
+    fn env(name: &str) -> Option<String> {
+        (name == "HTTP_PROXY").then(|| "http://proxy:3128".to_string())
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let template = "location: \"{{WORKTREE_PATH}}\"\nbranch: {{ BRANCH }}\nname: {{INSTANCE_NAME:-default}}\nproxy: {{ENV:HTTP_PROXY}}\nno_proxy: {{ENV:NO_PROXY:-localhost}}\n";
+        let rendered = render_with(template, &vars(), env).unwrap();
+        assert_eq!(
+            rendered,
+            "location: \"/repo-feature\"\nbranch: feature/a\nname: default\nproxy: http://proxy:3128\nno_proxy: localhost\n"
+        );
+    }
+
+    #[test]
+    fn test_render_keeps_lima_templates() {
+        let template = "mountPoint: \"{{.Home}}/data\"\nscript: echo {{ .User }} {{- if .Foo}}x{{end}} {{unclosed\n";
+        assert_eq!(render_with(template, &vars(), env).unwrap(), template);
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let err = render_with("a: {{UNKNOWN}}\nb: {{ENV:MISSING}}\n", &vars(), env)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("line 1: unknown placeholder {{UNKNOWN}}"));
+        assert!(err.contains("line 2: environment variable MISSING is not set"));
+        assert!(err.contains("Known placeholders: BRANCH, INSTANCE_NAME, WORKTREE_PATH"));
+    }
+}
@@ -42,6 +42,43 @@ pub fn start_proxy(instance_name: &str, local_port: u16) -> Result<Child> {
     Ok(child)
 }
 
+/// ゲストの 127.0.0.1:port をホストの 127.0.0.1:port に転送（ssh -R）
+pub fn start_reverse_forward(instance_name: &str, port: u16) -> Result<Child> {
+    let ssh_config = client::ssh_config_path(instance_name);
+
+    if !ssh_config.exists() {
+        anyhow::bail!(
+            "SSH config not found for instance '{}'. Is the VM running?",
+            instance_name
+        );
+    }
+
+    let host = format!("lima-{}", instance_name);
+    let mut child = Command::new("ssh")
+        .args([
+            "-F",
+            ssh_config.to_string_lossy().as_ref(),
+            "-N",
+            "-o",
+            "ExitOnForwardFailure=yes",
+            "-o",
+            "ControlMaster=no",
+            "-o",
+            "ControlPath=none",
+            "-R",
+            &format!("127.0.0.1:{}:127.0.0.1:{}", port, port),
+            &host,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to start SSH reverse forward")?;
+
+    ensure_forward_started(&mut child, "reverse forward")?;
+    Ok(child)
+}
+
 fn ensure_forward_started(child: &mut Child, label: &str) -> Result<()> {
     thread::sleep(Duration::from_millis(200));
     if let Some(status) = child.try_wait().context("Failed to check SSH status")? {
@@ -1,31 +1,359 @@
 use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 use std::path::Path;
 
+use super::placeholder;
+use crate::config::{
+    Config, NetworkCheckConfig, ProvisionScript, VmDocker, VmImage, VmMount, VmProbe,
+};
+use crate::utils;
+
+/// ホスト OS に応じたデフォルトの vmType（macOS: vz, それ以外: qemu）
+pub fn host_vm_type() -> &'static str {
+    if std::env::consts::OS == "macos" {
+        "vz"
+    } else {
+        "qemu"
+    }
+}
+
+/// ホストの CPU アーキテクチャ（Lima の arch 表記）
+pub fn host_arch() -> &'static str {
+    std::env::consts::ARCH
+}
+
+/// vm_type 設定値を検証して正規化
+fn normalize_vm_type(vm_type: &str) -> Result<String> {
+    match vm_type.trim() {
+        "vz" => {
+            if std::env::consts::OS != "macos" {
+                anyhow::bail!("vm_type \"vz\" requires a macOS host (use \"qemu\" instead)");
+            }
+            Ok("vz".to_string())
+        }
+        "qemu" => Ok("qemu".to_string()),
+        other => anyhow::bail!("Unsupported vm_type '{}'. Use vz or qemu.", other),
+    }
+}
+
+/// vm_arch 設定値を検証して正規化（arm64/amd64 の別名も受け付ける）
+fn normalize_arch(arch: &str) -> Result<String> {
+    match arch.trim() {
+        "aarch64" | "arm64" => Ok("aarch64".to_string()),
+        "x86_64" | "amd64" => Ok("x86_64".to_string()),
+        other => anyhow::bail!("Unsupported vm_arch '{}'. Use aarch64 or x86_64.", other),
+    }
+}
+
+/// vm_type とゲストのアーキテクチャの組み合わせを検証
+fn resolve_platform(vm_type: &str, explicit: bool, arch: &str, host_arch: &str) -> Result<String> {
+    if vm_type != "vz" || arch == host_arch {
+        return Ok(vm_type.to_string());
+    }
+    if explicit {
+        anyhow::bail!(
+            "vm_type \"vz\" cannot run a {} guest on a {} host (use vm_type = \"qemu\" or remove vm_arch)",
+            arch,
+            host_arch
+        );
+    }
+    Ok("qemu".to_string())
+}
+
+/// 内蔵デフォルトのベースイメージ（Ubuntu 24.04 LTS）
+fn default_images() -> Vec<VmImage> {
+    vec![
+        VmImage {
+            location: "https://cloud-images.ubuntu.com/releases/24.04/release/ubuntu-24.04-server-cloudimg-arm64.img".to_string(),
+            arch: Some("aarch64".to_string()),
+            digest: None,
+        },
+        VmImage {
+            location: "https://cloud-images.ubuntu.com/releases/24.04/release/ubuntu-24.04-server-cloudimg-amd64.img".to_string(),
+            arch: Some("x86_64".to_string()),
+            digest: None,
+        },
+    ]
+}
+
+/// イメージの location を解決する
+/// file:// やローカルパスは絶対パスに変換し、存在しなければエラーにする
+fn resolve_image_location(location: &str, base_dir: &Path) -> Result<String> {
+    let location = location.trim();
+    if location.contains("://") && !location.starts_with("file://") {
+        return Ok(location.to_string());
+    }
+
+    let raw = location.strip_prefix("file://").unwrap_or(location);
+    let mut path = utils::expand_home(raw);
+    if path.is_relative() {
+        path = base_dir.join(path);
+    }
+    if !path.is_file() {
+        anyhow::bail!("VM image not found: {}", path.display());
+    }
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// 共有パッケージキャッシュ（名前, ゲストのホームからの相対パス）
+const SHARED_CACHES: &[(&str, &str)] = &[
+    ("npm", ".npm"),
+    ("yarn", ".cache/yarn"),
+    ("pnpm", ".local/share/pnpm/store"),
+    ("pip", ".cache/pip"),
+    ("cargo", ".cargo/registry"),
+    ("go", "go/pkg/mod"),
+    ("maven", ".m2"),
+    ("gradle", ".gradle/caches"),
+];
+
+/// 共有キャッシュのホスト側ディレクトリ（全 fracta VM で共通）
+fn shared_cache_dir(name: &str) -> Result<std::path::PathBuf> {
+    Ok(utils::fracta_home_dir()?.join("shared-cache").join(name))
+}
+
+/// vm_network 設定値を検証して正規化
+fn normalize_network(network: &str) -> Result<String> {
+    match network.trim() {
+        "isolated" => Ok("isolated".to_string()),
+        "user-v2" => Ok("user-v2".to_string()),
+        "socket_vmnet" | "shared" => Ok("socket_vmnet".to_string()),
+        other => anyhow::bail!(
+            "Unsupported vm_network '{}'. Use isolated, user-v2 or socket_vmnet.",
+            other
+        ),
+    }
+}
+
+fn normalize_provision_mode(mode: &str) -> Result<String> {
+    match mode.trim().to_lowercase().as_str() {
+        "system" | "user" | "boot" | "dependency" => Ok(mode.trim().to_lowercase()),
+        other => anyhow::bail!(
+            "Unsupported provision mode '{}' (expected system, user, boot or dependency)",
+            other
+        ),
+    }
+}
+
+/// 環境変数名として使えるか
+fn is_env_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// シェルのシングルクォート文字列にする
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// ネットワーク確認の結果を置くファイル（起動毎にリセットされる /run 配下）
+const NETWORK_READY: &str = "/run/fracta/network-ready";
+const NETWORK_FAILED: &str = "/run/fracta/network-failed";
+
+/// ネットワーク確認のデフォルトの待ち時間（秒）
+const DEFAULT_NETWORK_CHECK_TIMEOUT: u64 = 300;
+
+/// provision スクリプト実行前のネットワーク確認
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkCheck {
+    /// 確認しない（オフライン環境など）
+    None,
+    /// 名前解決と TCP 接続を確認
+    Host { host: String, port: u16 },
+    /// 任意のコマンドが成功するまで待つ
+    Command(String),
+}
+
+impl NetworkCheck {
+    /// "host" または "host:port" をパース
+    fn parse_host(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let (host, port) = match value.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .context(format!("Invalid port in vm_network_check host '{}'", value))?,
+            ),
+            None => (value, 80),
+        };
+        if host.is_empty()
+            || !host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        {
+            anyhow::bail!("Invalid vm_network_check host '{}'", value);
+        }
+        Ok(NetworkCheck::Host {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// 成功すれば 0 を返すシェルコマンド
+    fn shell_test(&self) -> Option<String> {
+        match self {
+            NetworkCheck::None => None,
+            NetworkCheck::Host { host, port } => Some(format!(
+                "getent hosts {host} > /dev/null && timeout 5 bash -c '</dev/tcp/{host}/{port}'",
+                host = host,
+                port = port
+            )),
+            NetworkCheck::Command(command) => Some(format!("bash -c {}", shell_quote(command))),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            NetworkCheck::None => String::new(),
+            NetworkCheck::Host { host, port } => format!("cannot reach {}:{}", host, port),
+            NetworkCheck::Command(command) => format!("'{}' did not succeed", command),
+        }
+    }
+}
+
+/// vm_probes のデフォルトの待ち時間（秒）
+const DEFAULT_PROBE_TIMEOUT: u64 = 300;
+
+/// probe 失敗時に出力するマーカー（fracta がログから hint を取り出す）
+pub const PROBE_FAILED_MARKER: &str = "FRACTA_PROBE_FAILED:";
+
+/// ユーザー定義の readiness probe（vm_probes）
+#[derive(Debug, Clone)]
+pub struct UserProbe {
+    pub script: String,
+    pub hint: String,
+    pub timeout: u64,
+}
+
+/// provision スクリプト 1 件分の設定
+#[derive(Debug, Clone)]
+pub struct ProvisionStep {
+    pub content: String,
+    /// system / user / boot / dependency
+    pub mode: String,
+    pub env: BTreeMap<String, String>,
+    /// true なら起動毎に実行（false なら初回のみ）
+    pub always: bool,
+}
+
+impl ProvisionStep {
+    /// root で初回のみ実行するスクリプト
+    pub fn new(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            mode: "system".to_string(),
+            env: BTreeMap::new(),
+            always: false,
+        }
+    }
+
+    /// マーカーファイル名（従来の system / once / env なしはスクリプト内容のみで決まる）
+    fn hash(&self) -> String {
+        if self.mode == "system" && !self.always && self.env.is_empty() {
+            return simple_hash(&self.content);
+        }
+        let env: Vec<String> = self.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        simple_hash(&format!(
+            "{}\n{}\n{}\n{}",
+            self.mode,
+            self.always,
+            env.join("\n"),
+            self.content
+        ))
+    }
+
+    /// マーカーを置くディレクトリ（user モードはユーザーのホーム）
+    fn marker_dir(&self) -> &'static str {
+        if self.mode == "user" {
+            "$HOME/.local/state/fracta/provisioned"
+        } else {
+            "/var/lib/fracta/provisioned"
+        }
+    }
+
+    fn marker(&self) -> String {
+        format!("{}/{}", self.marker_dir(), self.hash())
+    }
+
+    /// 完了判定のシェル式（always は今回の起動で実行済みかを boot_id で判定）
+    fn ready_check(&self) -> String {
+        if self.always {
+            format!(
+                "[ \"$(cat \"{}\" 2>/dev/null)\" = \"$(cat /proc/sys/kernel/random/boot_id)\" ]",
+                self.marker()
+            )
+        } else {
+            format!("[ -f \"{}\" ]", self.marker())
+        }
+    }
+}
+
 /// Lima テンプレート設定
 #[derive(Debug, Clone)]
 pub struct TemplateConfig {
     pub worktree_path: String,
+    /// fracta のインスタンス名（{{INSTANCE_NAME}}）
+    pub instance_name: String,
+    /// worktree のブランチ（{{BRANCH}}）
+    pub branch: String,
+    /// メインリポジトリのパス（{{MAIN_REPO}}）
+    pub main_repo: String,
     pub cpus: u32,
     pub memory: String,
     pub disk: String,
     pub mount_type: String,
     pub user: String,
-    pub provision_scripts: Vec<String>,
+    /// vmType（vz / qemu）
+    pub vm_type: String,
+    /// ゲストのアーキテクチャ（None ならホストと同じ）
+    pub arch: Option<String>,
+    /// ネットワーク（isolated / user-v2 / socket_vmnet）
+    pub network: String,
+    /// ベースイメージ一覧
+    pub images: Vec<VmImage>,
+    /// worktree 以外の追加マウント（vm_mounts）
+    pub extra_mounts: Vec<VmMount>,
+    /// 共有パッケージキャッシュのマウント（vm_shared_caches）
+    pub shared_caches: Vec<VmMount>,
+    pub provision_scripts: Vec<ProvisionStep>,
     /// カスタムテンプレートファイルのパス（None ならデフォルト）
     pub custom_template: Option<String>,
+    /// テンプレートに深くマージする部分テンプレート（適用順）
+    pub overlays: Vec<Value>,
+    /// provision 前のネットワーク確認（vm_network_check）
+    pub network_check: NetworkCheck,
+    /// ネットワーク確認の待ち時間（秒）
+    pub network_check_timeout: u64,
+    /// ユーザー定義の readiness probe（vm_probes）
+    pub probes: Vec<UserProbe>,
 }
 
 impl Default for TemplateConfig {
     fn default() -> Self {
         Self {
             worktree_path: String::new(),
+            instance_name: String::new(),
+            branch: String::new(),
+            main_repo: String::new(),
             cpus: 4,
             memory: "8GiB".to_string(),
             disk: "50GiB".to_string(),
             mount_type: "virtiofs".to_string(),
             user: "lima".to_string(),
+            vm_type: host_vm_type().to_string(),
+            arch: None,
+            network: "isolated".to_string(),
+            images: default_images(),
+            extra_mounts: Vec::new(),
+            shared_caches: Vec::new(),
             provision_scripts: Vec::new(),
             custom_template: None,
+            overlays: Vec::new(),
+            network_check: NetworkCheck::Host {
+                host: "archive.ubuntu.com".to_string(),
+                port: 80,
+            },
+            network_check_timeout: DEFAULT_NETWORK_CHECK_TIMEOUT,
+            probes: Vec::new(),
         }
     }
 }
@@ -49,23 +377,306 @@ impl TemplateConfig {
         config
     }
 
+    /// fracta.toml の設定から、add / up で VM 作成に使うテンプレート設定を組み立てる
+    pub fn from_config(config: &Config, main_repo: &Path, worktree_path: &Path) -> Result<Self> {
+        let mut template_config = Self::new(
+            &worktree_path.to_string_lossy(),
+            config.vm_mount_type.as_deref(),
+            config.vm_user.as_deref(),
+        );
+        template_config.main_repo = main_repo.to_string_lossy().to_string();
+        template_config.set_platform(config.vm_type.as_deref(), config.vm_arch.as_deref())?;
+        if let Some(network) = config.vm_network.as_deref().filter(|n| !n.trim().is_empty()) {
+            template_config.network = normalize_network(network)?;
+        }
+        if let Some(images) = &config.vm_images {
+            template_config.set_images(images, main_repo)?;
+        }
+        if let Some(mounts) = &config.vm_mounts {
+            template_config.set_extra_mounts(mounts, main_repo)?;
+        }
+        if let Some(caches) = &config.vm_shared_caches {
+            template_config.set_shared_caches(caches)?;
+        }
+        template_config.resolve_template(config.vm_template.as_deref(), main_repo, worktree_path)?;
+        template_config.load_overlays(config, main_repo, worktree_path)?;
+        if let Some(scripts) = &config.vm_provision_scripts {
+            template_config.load_provision_scripts(scripts, main_repo)?;
+        }
+        if let Some(check) = &config.vm_network_check {
+            template_config.set_network_check(check)?;
+        }
+        if let Some(probes) = &config.vm_probes {
+            template_config.set_probes(probes)?;
+        }
+        if let Some(docker) = &config.vm_docker {
+            template_config.set_docker(docker)?;
+        }
+        Ok(template_config)
+    }
+
+    /// vm_network_check を設定
+    pub fn set_network_check(&mut self, check: &NetworkCheckConfig) -> Result<()> {
+        match check {
+            NetworkCheckConfig::Simple(value) if value.trim().eq_ignore_ascii_case("none") => {
+                self.network_check = NetworkCheck::None;
+            }
+            NetworkCheckConfig::Simple(value) => {
+                self.network_check = NetworkCheck::parse_host(value)?;
+            }
+            NetworkCheckConfig::Table(table) => {
+                self.network_check = match (&table.host, &table.command) {
+                    (Some(host), None) => NetworkCheck::parse_host(host)?,
+                    (None, Some(command)) if !command.trim().is_empty() => {
+                        NetworkCheck::Command(command.trim().to_string())
+                    }
+                    _ => anyhow::bail!("vm_network_check: set exactly one of 'host' or 'command'"),
+                };
+                if let Some(timeout) = &table.timeout {
+                    self.network_check_timeout = utils::parse_duration(timeout)
+                        .context(format!("Invalid vm_network_check timeout '{}'", timeout))?
+                        .as_secs();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// vm_probes を設定
+    pub fn set_probes(&mut self, probes: &[VmProbe]) -> Result<()> {
+        for (i, probe) in probes.iter().enumerate() {
+            if probe.script.trim().is_empty() {
+                anyhow::bail!("vm_probes[{}]: script is empty", i);
+            }
+            let timeout = match &probe.timeout {
+                Some(timeout) => utils::parse_duration(timeout)
+                    .context(format!("vm_probes[{}]: invalid timeout '{}'", i, timeout))?
+                    .as_secs(),
+                None => DEFAULT_PROBE_TIMEOUT,
+            };
+            let hint = probe
+                .hint
+                .as_deref()
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("vm_probes[{}] did not succeed", i));
+            self.probes.push(UserProbe {
+                script: probe.script.clone(),
+                hint,
+                timeout,
+            });
+        }
+        Ok(())
+    }
+
+    /// vm_docker を設定（Docker Engine のインストールを最初の provision スクリプトとして追加）
+    pub fn set_docker(&mut self, docker: &VmDocker) -> Result<()> {
+        if !docker.enable.unwrap_or(true) {
+            return Ok(());
+        }
+        let script = docker_provision_script(docker)?;
+        self.provision_scripts.insert(0, ProvisionStep::new(&script));
+        self.probes.insert(
+            0,
+            UserProbe {
+                script: "sudo docker info".to_string(),
+                hint: "Docker Engine is not running in the VM (vm_docker). Check 'fracta vm logs --provision'.".to_string(),
+                timeout: DEFAULT_PROBE_TIMEOUT,
+            },
+        );
+        Ok(())
+    }
+
+    /// provision スクリプトの前にネットワーク確認を行うか
+    fn needs_network_check(&self) -> bool {
+        self.network_check != NetworkCheck::None
+            && self
+                .provision_scripts
+                .iter()
+                .any(|step| waits_for_network_check(&step.mode))
+    }
+
+    /// インスタンス名とブランチを設定（カスタムテンプレートのプレースホルダ用）
+    pub fn set_instance(&mut self, instance_name: &str, branch: &str) {
+        self.instance_name = instance_name.to_string();
+        self.branch = branch.to_string();
+    }
+
+    /// vm_type / vm_arch を設定（未指定ならホストに合わせる）
+    ///
+    /// vz はホストと同じアーキテクチャしか動かせないため、vm_type 未指定で別アーキテクチャなら qemu にする。
+    pub fn set_platform(&mut self, vm_type: Option<&str>, arch: Option<&str>) -> Result<()> {
+        let explicit = vm_type.filter(|v| !v.trim().is_empty());
+        if let Some(vm_type) = explicit {
+            self.vm_type = normalize_vm_type(vm_type)?;
+        }
+        if let Some(arch) = arch.filter(|a| !a.trim().is_empty()) {
+            self.arch = Some(normalize_arch(arch)?);
+        }
+        self.vm_type = resolve_platform(
+            &self.vm_type,
+            explicit.is_some(),
+            self.guest_arch(),
+            host_arch(),
+        )?;
+        Ok(())
+    }
+
+    /// fracta.toml の vm_images でベースイメージを置き換える
+    pub fn set_images(&mut self, images: &[VmImage], base_dir: &Path) -> Result<()> {
+        if images.is_empty() {
+            return Ok(());
+        }
+        let mut resolved = Vec::new();
+        for image in images {
+            resolved.push(VmImage {
+                location: resolve_image_location(&image.location, base_dir)?,
+                arch: match &image.arch {
+                    Some(arch) => Some(normalize_arch(arch)?),
+                    None => None,
+                },
+                digest: match &image.digest {
+                    Some(digest) => Some(format!("sha256:{}", utils::parse_digest(digest)?)),
+                    None => None,
+                },
+            });
+        }
+        self.images = resolved;
+        Ok(())
+    }
+
+    /// fracta.toml の vm_mounts を追加マウントとして設定
+    pub fn set_extra_mounts(&mut self, mounts: &[VmMount], base_dir: &Path) -> Result<()> {
+        let mut resolved = Vec::new();
+        for mount in mounts {
+            let mut location = utils::expand_home(mount.location.trim());
+            if location.is_relative() {
+                location = base_dir.join(location);
+            }
+            resolved.push(VmMount {
+                location: location.to_string_lossy().to_string(),
+                mount_point: mount.mount_point.clone(),
+                writable: mount.writable,
+            });
+        }
+        self.extra_mounts = resolved;
+        Ok(())
+    }
+
+    /// 共有パッケージキャッシュを設定（"all" で全種類）
+    pub fn set_shared_caches(&mut self, names: &[String]) -> Result<()> {
+        let mut selected = Vec::new();
+        for name in names {
+            let name = name.trim();
+            if name == "all" {
+                selected = SHARED_CACHES.to_vec();
+                break;
+            }
+            let entry = SHARED_CACHES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .with_context(|| {
+                    let known: Vec<&str> = SHARED_CACHES.iter().map(|(n, _)| *n).collect();
+                    format!(
+                        "Unknown shared cache '{}'. Available: {}, all",
+                        name,
+                        known.join(", ")
+                    )
+                })?;
+            if !selected.contains(entry) {
+                selected.push(*entry);
+            }
+        }
+
+        let mut mounts = Vec::new();
+        for (name, guest_rel) in selected {
+            mounts.push(VmMount {
+                location: shared_cache_dir(name)?.to_string_lossy().to_string(),
+                mount_point: Some(format!("{{{{.Home}}}}/{}", guest_rel)),
+                writable: true,
+            });
+        }
+        self.shared_caches = mounts;
+        Ok(())
+    }
+
+    /// 追加マウント・共有キャッシュのホスト側ディレクトリを用意する
+    pub fn prepare_mount_sources(&self) -> Result<()> {
+        for mount in &self.extra_mounts {
+            if !Path::new(&mount.location).exists() {
+                anyhow::bail!("Mount location not found: {}", mount.location);
+            }
+        }
+        for mount in &self.shared_caches {
+            std::fs::create_dir_all(&mount.location).context(format!(
+                "Failed to create shared cache directory: {}",
+                mount.location
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// ゲストのアーキテクチャ（未指定ならホストと同じ）
+    pub fn guest_arch(&self) -> &str {
+        self.arch.as_deref().unwrap_or(host_arch())
+    }
+
+    /// Rosetta を有効にするか（vz かつ Apple Silicon 上の aarch64 ゲストのみ）
+    fn rosetta_enabled(&self) -> bool {
+        self.vm_type == "vz" && host_arch() == "aarch64" && self.guest_arch() == "aarch64"
+    }
+
     /// fracta.toml の vm_provision_scripts からスクリプト内容を読み込む
     pub fn load_provision_scripts(
         &mut self,
-        script_paths: &[String],
+        scripts: &[ProvisionScript],
         base_dir: &Path,
     ) -> Result<()> {
-        for path_str in script_paths {
-            let script_path = if Path::new(path_str).is_absolute() {
-                std::path::PathBuf::from(path_str)
-            } else {
-                base_dir.join(path_str)
+        for (i, entry) in scripts.iter().enumerate() {
+            let step = match entry {
+                ProvisionScript::Path(path) => {
+                    ProvisionStep::new(&read_provision_script(path, base_dir)?)
+                }
+                ProvisionScript::Table(table) => {
+                    let content = match (&table.path, &table.script) {
+                        (Some(path), None) => read_provision_script(path, base_dir)?,
+                        (None, Some(script)) => script.clone(),
+                        _ => anyhow::bail!(
+                            "vm_provision_scripts[{}]: set exactly one of 'path' or 'script'",
+                            i
+                        ),
+                    };
+                    let mode = match table.mode.as_deref() {
+                        Some(mode) => normalize_provision_mode(mode)
+                            .context(format!("vm_provision_scripts[{}]", i))?,
+                        None => "system".to_string(),
+                    };
+                    let always = match table.run.as_deref().map(str::trim) {
+                        None | Some("once") => false,
+                        Some("always") => true,
+                        Some(other) => anyhow::bail!(
+                            "vm_provision_scripts[{}]: unsupported run '{}' (expected once or always)",
+                            i,
+                            other
+                        ),
+                    };
+                    if let Some(name) = table.env.keys().find(|k| !is_env_name(k)) {
+                        anyhow::bail!(
+                            "vm_provision_scripts[{}]: invalid environment variable name '{}'",
+                            i,
+                            name
+                        );
+                    }
+                    ProvisionStep {
+                        content,
+                        mode,
+                        env: table.env.clone(),
+                        always,
+                    }
+                }
             };
-            let content = std::fs::read_to_string(&script_path).context(format!(
-                "Failed to read provision script: {}",
-                script_path.display()
-            ))?;
-            self.provision_scripts.push(content);
+            self.provision_scripts.push(step);
         }
         Ok(())
     }
@@ -77,17 +688,19 @@ impl TemplateConfig {
         vm_template: Option<&str>,
         main_repo: &Path,
         worktree_path: &Path,
-    ) {
-        if let Some(path) = vm_template {
-            let resolved = if Path::new(path).is_absolute() {
-                std::path::PathBuf::from(path)
+    ) -> Result<()> {
+        if let Some(path) = vm_template.filter(|p| !p.trim().is_empty()) {
+            let resolved = utils::expand_home(path);
+            let resolved = if resolved.is_absolute() {
+                resolved
             } else {
-                main_repo.join(path)
+                main_repo.join(resolved)
             };
-            if resolved.exists() {
-                self.custom_template = Some(resolved.to_string_lossy().to_string());
-                return;
+            if !resolved.is_file() {
+                anyhow::bail!("vm_template not found: {}", resolved.display());
             }
+            self.custom_template = Some(resolved.to_string_lossy().to_string());
+            return Ok(());
         }
 
         // .fracta/lima-template.yaml を探す（worktree → main_repo の順）
@@ -95,10 +708,176 @@ impl TemplateConfig {
             let candidate = dir.join(".fracta").join("lima-template.yaml");
             if candidate.exists() {
                 self.custom_template = Some(candidate.to_string_lossy().to_string());
-                return;
+                return Ok(());
             }
         }
+        Ok(())
+    }
+
+    /// カスタムテンプレートで使えるプレースホルダの値
+    fn placeholder_vars(&self) -> BTreeMap<&'static str, String> {
+        BTreeMap::from([
+            ("WORKTREE_PATH", self.worktree_path.clone()),
+            ("INSTANCE_NAME", self.instance_name.clone()),
+            ("BRANCH", self.branch.clone()),
+            ("MAIN_REPO", self.main_repo.clone()),
+            ("USER", self.user.clone()),
+            ("CPUS", self.cpus.to_string()),
+            ("MEMORY", self.memory.clone()),
+            ("DISK", self.disk.clone()),
+            ("HOST_ARCH", host_arch().to_string()),
+            ("ARCH", self.guest_arch().to_string()),
+            ("VM_TYPE", self.vm_type.clone()),
+        ])
+    }
+
+    /// テンプレートオーバーレイを読み込む
+    /// 適用順: .fracta/lima-template.overlay.yaml → fracta.toml の [vm.template_overlay]
+    pub fn load_overlays(
+        &mut self,
+        config: &Config,
+        main_repo: &Path,
+        worktree_path: &Path,
+    ) -> Result<()> {
+        // .fracta/lima-template.overlay.yaml を探す（worktree → main_repo の順）
+        for dir in &[worktree_path, main_repo] {
+            let candidate = dir.join(".fracta").join("lima-template.overlay.yaml");
+            if !candidate.exists() {
+                continue;
+            }
+            let content = std::fs::read_to_string(&candidate).context(format!(
+                "Failed to read template overlay: {}",
+                candidate.display()
+            ))?;
+            let overlay: Value = serde_yaml::from_str(&content).context(format!(
+                "Failed to parse template overlay: {}",
+                candidate.display()
+            ))?;
+            self.add_overlay(overlay)
+                .context(format!("Invalid template overlay: {}", candidate.display()))?;
+            break;
+        }
+
+        if let Some(overlay) = config.vm.as_ref().and_then(|vm| vm.template_overlay.as_ref()) {
+            let overlay = serde_yaml::to_value(overlay)
+                .context("Failed to convert [vm.template_overlay]")?;
+            self.add_overlay(overlay)
+                .context("Invalid [vm.template_overlay] in fracta.toml")?;
+        }
+        Ok(())
+    }
+
+    fn add_overlay(&mut self, overlay: Value) -> Result<()> {
+        match overlay {
+            Value::Null => Ok(()),
+            Value::Mapping(_) => {
+                self.overlays.push(overlay);
+                Ok(())
+            }
+            _ => anyhow::bail!("Template overlay must be a YAML mapping"),
+        }
+    }
+}
+
+fn read_provision_script(path: &str, base_dir: &Path) -> Result<String> {
+    let script_path = if Path::new(path).is_absolute() {
+        std::path::PathBuf::from(path)
+    } else {
+        base_dir.join(path)
+    };
+    std::fs::read_to_string(&script_path).context(format!(
+        "Failed to read provision script: {}",
+        script_path.display()
+    ))
+}
+
+/// apt-cache madison の出力からバージョンを選ぶ grep パターン（例: 27.3.1 → ^([0-9]+:)?27\.3\.1-）
+fn docker_version_pattern(version: &str) -> Result<String> {
+    let version = version.trim();
+    if version.is_empty()
+        || !version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '~' | '+'))
+    {
+        anyhow::bail!("Invalid version '{}' in vm_docker", version);
+    }
+    Ok(format!("^([0-9]+:)?{}-", version.replace('.', "\\.").replace('+', "\\+")))
+}
+
+/// Docker Engine をインストールする provision スクリプト（download.docker.com の apt リポジトリ）
+fn docker_provision_script(docker: &VmDocker) -> Result<String> {
+    let mut script = String::from(
+        r#"# Docker Engine (vm_docker)
+export DEBIAN_FRONTEND=noninteractive
+apt-get update
+apt-get install -y ca-certificates curl
+install -m 0755 -d /etc/apt/keyrings
+curl -fsSL https://download.docker.com/linux/ubuntu/gpg -o /etc/apt/keyrings/docker.asc
+chmod a+r /etc/apt/keyrings/docker.asc
+echo "deb [arch=$(dpkg --print-architecture) signed-by=/etc/apt/keyrings/docker.asc] https://download.docker.com/linux/ubuntu $(. /etc/os-release && echo "$VERSION_CODENAME") stable" > /etc/apt/sources.list.d/docker.list
+apt-get update
+pick_version() {
+  local version
+  version=$(apt-cache madison "$1" | awk '{print $3}' | grep -E "$2" | head -n 1)
+  if [ -z "$version" ]; then
+    echo "No $1 package matches $2" >&2
+    return 1
+  fi
+  echo "$version"
+}
+"#,
+    );
+
+    let mut packages = vec!["containerd.io".to_string(), "docker-buildx-plugin".to_string()];
+    let mut held = Vec::new();
+    match &docker.version {
+        Some(version) => {
+            script.push_str(&format!(
+                "DOCKER_VERSION=$(pick_version docker-ce '{}')\n",
+                docker_version_pattern(version)?
+            ));
+            packages.push("docker-ce=\"$DOCKER_VERSION\"".to_string());
+            packages.push("docker-ce-cli=\"$DOCKER_VERSION\"".to_string());
+            held.extend(["docker-ce", "docker-ce-cli"]);
+        }
+        None => packages.extend(["docker-ce".to_string(), "docker-ce-cli".to_string()]),
     }
+    match &docker.compose_version {
+        Some(version) => {
+            script.push_str(&format!(
+                "COMPOSE_VERSION=$(pick_version docker-compose-plugin '{}')\n",
+                docker_version_pattern(version)?
+            ));
+            packages.push("docker-compose-plugin=\"$COMPOSE_VERSION\"".to_string());
+            held.push("docker-compose-plugin");
+        }
+        None => packages.push("docker-compose-plugin".to_string()),
+    }
+
+    script.push_str(&format!(
+        "apt-get install -y --allow-downgrades {}\n",
+        packages.join(" ")
+    ));
+    if !held.is_empty() {
+        script.push_str(&format!("apt-mark hold {}\n", held.join(" ")));
+    }
+
+    if let Some(daemon) = &docker.daemon {
+        let json = serde_json::to_string_pretty(daemon)
+            .context("Failed to convert vm_docker.daemon to JSON")?;
+        script.push_str(&format!(
+            "mkdir -p /etc/docker\ncat > /etc/docker/daemon.json <<'FRACTA_EOF'\n{}\nFRACTA_EOF\n",
+            json
+        ));
+    }
+
+    script.push_str(
+        r#"systemctl enable docker
+systemctl restart docker
+usermod -aG docker "$FRACTA_VM_USER"
+"#,
+    );
+    Ok(script)
 }
 
 /// provision セクションを生成
@@ -116,79 +895,260 @@ fn generate_provision(config: &TemplateConfig) -> String {
 "#,
     );
 
-    provisions.push_str(&generate_user_provisions(&config.provision_scripts));
+    provisions.push_str(&generate_user_provisions(config));
 
     provisions
 }
 
-/// ユーザー指定のプロビジョニングスクリプト（冪等性マーカー付き）を生成
-fn generate_user_provisions(scripts: &[String]) -> String {
-    let mut provisions = String::new();
-
-    for (i, script) in scripts.iter().enumerate() {
-        let hash = simple_hash(script);
-        let marker = format!("/var/lib/fracta/provisioned/{}", hash);
+/// ネットワーク確認の provision エントリを生成（結果を /run/fracta に書き出す）
+fn generate_network_check(config: &TemplateConfig) -> String {
+    let test = match config.network_check.shell_test() {
+        Some(test) if config.needs_network_check() => test,
+        _ => return String::new(),
+    };
 
-        let indented_body = indent_script(script);
-        provisions.push_str(&format!(
-            r#"  - mode: system
+    format!(
+        r#"  - mode: system
     script: |
       #!/bin/bash
-      set -eux -o pipefail
-      # Provision script {} (hash: {})
-      export FRACTA_VM_USER="{{{{.User}}}}"
-      if [ -f '{}' ]; then
-        echo "Already provisioned ({}), skipping"
-        exit 0
-      fi
-      # Wait for DNS to be available
-      echo "Waiting for network..."
-      for i in $(seq 1 60); do
-        if nslookup archive.ubuntu.com > /dev/null 2>&1; then
-          echo "Network ready"
+      # Network readiness check (vm_network_check)
+      mkdir -p /run/fracta
+      rm -f {ready} {failed}
+      echo "Checking network..."
+      deadline=$((SECONDS + {timeout}))
+      until ( {test} ) > /dev/null 2>&1; do
+        if [ "$SECONDS" -ge "$deadline" ]; then
+          echo {description} > {failed}
+          echo "Network check failed: $(cat {failed})"
+          exit 0
+        fi
+        sleep 5
+      done
+      echo "Network ready"
+      touch {ready}
+"#,
+        ready = NETWORK_READY,
+        failed = NETWORK_FAILED,
+        timeout = config.network_check_timeout,
+        test = test,
+        description = shell_quote(&config.network_check.describe()),
+    )
+}
+
+/// ネットワーク確認のエントリ（mode: system）の結果を待つ mode か
+///
+/// boot はネットワーク起動前、dependency は system より前に実行されるため結果を待てない。
+fn waits_for_network_check(mode: &str) -> bool {
+    mode == "system" || mode == "user"
+}
+
+/// dependency スクリプトで、ネットワーク確認をスクリプト内で直接行う
+fn network_poll_block(config: &TemplateConfig) -> String {
+    let test = match config.network_check.shell_test() {
+        Some(test) => test,
+        None => return String::new(),
+    };
+    format!(
+        r#"      # Check the network (vm_network_check, runs before the system check entry)
+      deadline=$((SECONDS + {timeout}))
+      until ( {test} ) > /dev/null 2>&1; do
+        if [ "$SECONDS" -ge "$deadline" ]; then
+          echo "Warning: network check failed: "{description}
           break
         fi
         sleep 5
       done
-{}
-      mkdir -p /var/lib/fracta/provisioned
-      touch '{}'
 "#,
-            i + 1,
-            hash,
-            marker,
-            hash,
-            indented_body,
-            marker,
+        timeout = config.network_check_timeout,
+        test = test,
+        description = shell_quote(&config.network_check.describe()),
+    )
+}
+
+/// 各 provision スクリプトでネットワーク確認の結果を待つ
+fn network_wait_block(config: &TemplateConfig) -> String {
+    format!(
+        r#"      # Wait for the network check (vm_network_check)
+      for i in $(seq 1 {attempts}); do
+        [ -f {ready} ] && break
+        if [ -f {failed} ]; then
+          echo "Warning: network check failed: $(cat {failed})"
+          break
+        fi
+        sleep 5
+      done
+"#,
+        attempts = config.network_check_timeout / 5 + 6,
+        ready = NETWORK_READY,
+        failed = NETWORK_FAILED,
+    )
+}
+
+/// ユーザー指定のプロビジョニングスクリプト（冪等性マーカー付き）を生成
+fn generate_user_provisions(config: &TemplateConfig) -> String {
+    let mut provisions = generate_network_check(config);
+    let network_check = config.needs_network_check();
+
+    for (i, step) in config.provision_scripts.iter().enumerate() {
+        let hash = step.hash();
+        let marker = step.marker();
+
+        let mut prelude = String::from("      export FRACTA_VM_USER=\"{{.User}}\"\n");
+        for (key, value) in &step.env {
+            prelude.push_str(&format!("      export {}={}\n", key, shell_quote(value)));
+        }
+        if !step.always {
+            prelude.push_str(&format!(
+                r#"      if [ -f "{}" ]; then
+        echo "Already provisioned ({}), skipping"
+        exit 0
+      fi
+"#,
+                marker, hash
+            ));
+        }
+        // boot スクリプトはネットワーク起動前、dependency は確認エントリより前に実行される
+        if network_check && waits_for_network_check(&step.mode) {
+            prelude.push_str(&network_wait_block(config));
+        } else if step.mode == "dependency" {
+            prelude.push_str(&network_poll_block(config));
+        }
+        let done = if step.always {
+            format!("cat /proc/sys/kernel/random/boot_id > \"{}\"", marker)
+        } else {
+            format!("touch \"{}\"", marker)
+        };
+
+        provisions.push_str(&format!(
+            r#"  - mode: {mode}
+    script: |
+      #!/bin/bash
+      set -eux -o pipefail
+      # Provision script {index} ({mode}, {run}, hash: {hash})
+{prelude}{body}
+      mkdir -p "{dir}"
+      {done}
+"#,
+            mode = step.mode,
+            index = i + 1,
+            run = if step.always { "always" } else { "once" },
+            hash = hash,
+            prelude = prelude,
+            body = indent_script(&step.content),
+            dir = step.marker_dir(),
+            done = done,
         ));
     }
 
     provisions
 }
 
-/// probe セクションを生成（provision スクリプトがある場合のみ）
-fn generate_probes(scripts: &[String]) -> String {
-    if scripts.is_empty() {
+/// probe エントリを生成
+/// ネットワーク確認の結果を hint 付きで報告し、全スクリプトのマーカーが揃うまで待ってから
+/// vm_probes を順に確認する
+fn generate_probe_entries(config: &TemplateConfig) -> String {
+    let mut probes = generate_provision_probes(config);
+    probes.push_str(&generate_user_probes(&config.probes));
+    probes
+}
+
+/// provision 完了を待つ probe エントリを生成（provision スクリプトがある場合のみ）
+fn generate_provision_probes(config: &TemplateConfig) -> String {
+    let steps = &config.provision_scripts;
+    if steps.is_empty() {
         return String::new();
     }
 
-    let last_hash = simple_hash(scripts.last().unwrap());
-    let marker = format!("/var/lib/fracta/provisioned/{}", last_hash);
+    let mut probes = String::new();
+    if config.needs_network_check() {
+        let hint = format!(
+            "Network check before provisioning failed ({}). Check DNS / proxy / mirror settings in the VM, or adjust vm_network_check in fracta.toml (\"none\" to disable).",
+            config.network_check.describe()
+        );
+        probes.push_str(&format!(
+            r#"  - script: |
+      #!/bin/bash
+      if ! timeout {timeout}s bash -c "until [ -f {ready} ] || [ -f {failed} ]; do sleep 2; done"; then
+        echo "Network check did not finish in time"
+        exit 1
+      fi
+      if [ -f {failed} ]; then
+        echo "Network check failed: $(cat {failed})"
+        exit 1
+      fi
+    hint: {hint}
+"#,
+            timeout = config.network_check_timeout + 60,
+            ready = NETWORK_READY,
+            failed = NETWORK_FAILED,
+            hint = serde_json::to_string(&hint).unwrap_or_default(),
+        ));
+    }
+
+    let checks: Vec<String> = steps
+        .iter()
+        .map(|step| format!("        {} || return 1\n", step.ready_check()))
+        .collect();
 
-    format!(
-        r#"
-# Wait for provisioning to complete
-probes:
-  - script: |
+    probes.push_str(&format!(
+        r#"  - script: |
       #!/bin/bash
-      if ! timeout 600s bash -c "until [ -f '{}' ]; do sleep 5; done"; then
+      fracta_provisioned() {{
+{}      }}
+      export -f fracta_provisioned
+      if ! timeout 600s bash -c "until fracta_provisioned; do sleep 5; done"; then
         echo "Provisioning did not complete in time"
         exit 1
       fi
     hint: "Waiting for provisioning to complete..."
 "#,
-        marker,
-    )
+        checks.concat(),
+    ));
+    probes
+}
+
+/// vm_probes の probe エントリを生成（成功するまで繰り返し、期限切れで hint を出力）
+fn generate_user_probes(probes: &[UserProbe]) -> String {
+    let mut entries = String::new();
+    for (i, probe) in probes.iter().enumerate() {
+        entries.push_str(&format!(
+            r#"  - script: |
+      #!/bin/bash
+      # vm_probes[{index}]
+      fracta_probe() {{
+{body}
+      }}
+      deadline=$((SECONDS + {timeout}))
+      until ( fracta_probe ) > /dev/null 2>&1; do
+        if [ "$SECONDS" -ge "$deadline" ]; then
+          echo {failed}
+          exit 1
+        fi
+        sleep 3
+      done
+    hint: {hint}
+"#,
+            index = i,
+            body = indent_script(&probe.script)
+                .lines()
+                .map(|line| format!("  {}", line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            timeout = probe.timeout,
+            failed = shell_quote(&format!("{} {}", PROBE_FAILED_MARKER, probe.hint)),
+            hint = serde_json::to_string(&probe.hint).unwrap_or_default(),
+        ));
+    }
+    entries
+}
+
+/// probe セクションを生成（probe がある場合のみ）
+fn generate_probes(config: &TemplateConfig) -> String {
+    let entries = generate_probe_entries(config);
+    if entries.is_empty() {
+        return entries;
+    }
+    format!("\n# Wait for provisioning to complete\nprobes:\n{}", entries)
 }
 
 /// スクリプトを provision ブロック内のインデントに合わせる
@@ -224,6 +1184,98 @@ pub fn simple_hash(s: &str) -> String {
     format!("{:016x}", hash)
 }
 
+/// vm_mounts / vm_shared_caches の mounts エントリを生成
+///
+/// Lima の mountType は VM 全体で 1 つのため、マウント毎のオプションは
+/// テンプレート全体の mountType に合わせて出力する。
+fn generate_extra_mounts(config: &TemplateConfig, mount_type: Option<&str>) -> String {
+    let mut block = String::new();
+    for mount in config.extra_mounts.iter().chain(&config.shared_caches) {
+        block.push_str(&format!("  - location: \"{}\"\n", mount.location));
+        if let Some(mount_point) = &mount.mount_point {
+            block.push_str(&format!("    mountPoint: \"{}\"\n", mount_point));
+        }
+        block.push_str(&format!("    writable: {}\n", mount.writable));
+        match mount_type {
+            Some("sshfs") => block.push_str("    sshfs:\n      cache: true\n      followSymlinks: true\n"),
+            Some("9p") => block.push_str("    9p:\n      cache: \"mmap\"\n"),
+            _ => {}
+        }
+    }
+    block
+}
+
+/// images セクションを生成
+fn generate_images(images: &[VmImage]) -> String {
+    let mut block = String::new();
+    for image in images {
+        block.push_str(&format!("  - location: \"{}\"\n", image.location));
+        if let Some(arch) = &image.arch {
+            block.push_str(&format!("    arch: \"{}\"\n", arch));
+        }
+        if let Some(digest) = &image.digest {
+            block.push_str(&format!("    digest: \"{}\"\n", digest));
+        }
+    }
+    block
+}
+
+/// VM 間で共有するネットワークの networks エントリ（isolated なら None）
+fn shared_network_entry(config: &TemplateConfig) -> Option<&'static str> {
+    match config.network.as_str() {
+        "user-v2" => Some("  - lima: user-v2\n"),
+        "socket_vmnet" => Some("  - lima: shared\n"),
+        _ => None,
+    }
+}
+
+/// 作成済みテンプレートから VM 間共有ネットワークのゲスト側インターフェース名を求める
+///
+/// Lima は networks の各エントリを順に lima0, lima1, ... として VM に割り当てるため、
+/// カスタムテンプレートに既存の networks があると共有ネットワークは lima0 とは限らない。
+pub fn shared_interface(template: &str) -> Option<String> {
+    let document: Value = serde_yaml::from_str(template).ok()?;
+    let networks = document.get("networks")?.as_sequence()?;
+    networks
+        .iter()
+        .position(|entry| {
+            matches!(
+                entry.get("lima").and_then(Value::as_str),
+                Some("user-v2") | Some("shared")
+            )
+        })
+        .map(|index| format!("lima{}", index))
+}
+
+/// vmType / rosetta / networks セクションを生成（ホスト OS に依存）
+fn generate_platform(config: &TemplateConfig) -> String {
+    let mut block = String::new();
+
+    if config.vm_type == "vz" {
+        block.push_str("# VM type: vz (macOS Virtualization.framework)\nvmType: \"vz\"\n");
+        if config.rosetta_enabled() {
+            block.push_str("rosetta:\n  enabled: true\n  binfmt: true\n");
+        }
+    } else {
+        block.push_str("# VM type: qemu (Linux host)\nvmType: \"qemu\"\n");
+    }
+
+    match shared_network_entry(config) {
+        Some(entry) => {
+            block.push_str("\n# Network configuration: shared between fracta VMs\nnetworks:\n");
+            block.push_str(entry);
+        }
+        None if config.vm_type == "vz" => {
+            block.push_str("\n# Network configuration\nnetworks:\n  - vzNAT: true\n");
+        }
+        None => {
+            block.push_str("\n# Network configuration: user-mode networking (slirp, Lima default)\n");
+        }
+    }
+
+    block
+}
+
 /// デフォルトの Lima VM テンプレートを生成
 pub fn generate_default(config: &TemplateConfig) -> String {
     let mount_block = if config.mount_type == "sshfs" {
@@ -245,15 +1297,27 @@ pub fn generate_default(config: &TemplateConfig) -> String {
         )
     };
 
+    let arch_line = match &config.arch {
+        Some(arch) => format!("arch: \"{}\"\n", arch),
+        None => String::new(),
+    };
+
+    let mount_block = format!(
+        "{}{}",
+        mount_block,
+        generate_extra_mounts(config, Some(config.mount_type.as_str()))
+    );
+    let images_block = generate_images(&config.images);
+    let platform_block = generate_platform(config);
     let provision_block = generate_provision(config);
-    let probes_block = generate_probes(&config.provision_scripts);
+    let probes_block = generate_probes(config);
 
     format!(
         r#"# fracta Lima VM template
 # Auto-generated for worktree development
 
 # VM configuration
-cpus: {cpus}
+{arch_line}cpus: {cpus}
 memory: "{memory}"
 disk: "{disk}"
 
@@ -261,26 +1325,15 @@ disk: "{disk}"
 user:
   name: "{user}"
 
-# Use Ubuntu 24.04 LTS
+# Base images (default: Ubuntu 24.04 LTS, override with vm_images)
 images:
-  - location: "https://cloud-images.ubuntu.com/releases/24.04/release/ubuntu-24.04-server-cloudimg-arm64.img"
-    arch: "aarch64"
-  - location: "https://cloud-images.ubuntu.com/releases/24.04/release/ubuntu-24.04-server-cloudimg-amd64.img"
-    arch: "x86_64"
+{images_block}
 
-# Mount worktree directory
+# Mount worktree directory (and vm_mounts / vm_shared_caches)
 mounts:
 {mount_block}
 
-# VM type: vz for Apple Silicon
-vmType: "vz"
-rosetta:
-  enabled: true
-  binfmt: true
-
-# Network configuration
-networks:
-  - vzNAT: true
+{platform_block}
 
 # Disable automatic port forwarding (use fracta forward instead)
 portForwards:
@@ -297,101 +1350,184 @@ containerd:
 provision:
 {provision_block}{probes_block}
 "#,
+        arch_line = arch_line,
         cpus = config.cpus,
         memory = config.memory,
         disk = config.disk,
         user = config.user,
+        images_block = images_block.trim_end(),
         mount_block = mount_block.trim_end(),
+        platform_block = platform_block.trim_end(),
         provision_block = provision_block.trim_end(),
         probes_block = probes_block,
     )
 }
 
-/// カスタムテンプレートに provision スクリプトを注入
-fn inject_provisions_into_template(
-    template: &str,
-    scripts: &[String],
-) -> String {
-    if scripts.is_empty() {
-        return template.to_string();
+/// 生成したリスト形式の YAML ブロック（"  - ..."）をエントリ列に変換
+fn parse_entries(block: &str) -> Result<Vec<Value>> {
+    if block.trim().is_empty() {
+        return Ok(Vec::new());
     }
+    serde_yaml::from_str(block).context("Failed to build template entries")
+}
 
-    let user_provisions = generate_user_provisions(scripts);
-    let probes = generate_probes(scripts);
+/// トップレベルのリストにエントリを追加（キーがなければ作成）
+fn append_entries(root: &mut Mapping, key: &str, entries: Vec<Value>) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let slot = root
+        .entry(Value::String(key.to_string()))
+        .or_insert(Value::Null);
+    if slot.is_null() {
+        *slot = Value::Sequence(Vec::new());
+    }
+    match slot {
+        Value::Sequence(seq) => {
+            seq.extend(entries);
+            Ok(())
+        }
+        _ => anyhow::bail!("'{}' in custom Lima template must be a list", key),
+    }
+}
 
-    let mut result = template.to_string();
+/// カスタムテンプレートに provision スクリプトと追加マウントを注入
+fn inject_provisions_into_template(template: &str, config: &TemplateConfig) -> Result<String> {
+    let document: Value =
+        serde_yaml::from_str(template).context("Failed to parse custom Lima template as YAML")?;
+    let mut root = match document {
+        Value::Mapping(map) => map,
+        Value::Null => Mapping::new(),
+        _ => anyhow::bail!("Custom Lima template must be a YAML mapping at the top level"),
+    };
 
-    // provision: セクションがあればその末尾に追加
-    if let Some(pos) = find_yaml_section_end(&result, "provision:") {
-        result.insert_str(pos, &user_provisions);
-    } else {
-        // provision セクションがなければ末尾に追加
-        result.push_str("\nprovision:\n");
-        result.push_str(&user_provisions);
-    }
-
-    // probes セクションを追加（既存があれば末尾に）
-    if let Some(pos) = find_yaml_section_end(&result, "probes:") {
-        let probe_entries = probes
-            .trim_start_matches('\n')
-            .trim_start_matches("# Wait for provisioning to complete\n")
-            .trim_start_matches("probes:\n");
-        result.insert_str(pos, probe_entries);
-    } else {
-        result.push_str(&probes);
+    let mount_type = root.get("mountType").and_then(Value::as_str).map(str::to_string);
+    let extra_mounts = generate_extra_mounts(config, mount_type.as_deref());
+    append_entries(&mut root, "mounts", parse_entries(&extra_mounts)?)?;
+    if let Some(entry) = shared_network_entry(config) {
+        append_entries(&mut root, "networks", parse_entries(entry)?)?;
     }
 
-    result
+    append_entries(&mut root, "provision", parse_entries(&generate_user_provisions(config))?)?;
+    append_entries(&mut root, "probes", parse_entries(&generate_probe_entries(config))?)?;
+
+    serde_yaml::to_string(&Value::Mapping(root)).context("Failed to serialize Lima template")
 }
 
-/// YAML のトップレベルセクションの末尾位置を見つける
-fn find_yaml_section_end(yaml: &str, section: &str) -> Option<usize> {
-    let section_start = yaml.find(section)?;
-    let after_section = section_start + section.len();
+/// mounts エントリを location で照合するキー
+fn mount_location(entry: &Value) -> Option<&str> {
+    entry.get("location").and_then(Value::as_str)
+}
 
-    // セクション開始以降の行を見て、次のトップレベルキーを探す
-    let remaining = &yaml[after_section..];
-    for (i, line) in remaining.lines().enumerate() {
-        if i == 0 {
-            continue; // セクション行自体はスキップ
-        }
-        // 空行でもインデントされた行でもない = 次のトップレベルキー
-        if !line.is_empty()
-            && !line.starts_with(' ')
-            && !line.starts_with('#')
-            && !line.starts_with('\t')
-        {
-            // この行の開始位置を返す
-            let line_start = remaining[..remaining.find(line).unwrap_or(0)].len();
-            return Some(after_section + line_start);
+/// オーバーレイの値を再帰的にマージ（マッピングは深くマージ、それ以外は置き換え）
+fn merge_value(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                if value.is_null() {
+                    base.remove(&key);
+                    continue;
+                }
+                match base.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
         }
+        (base, overlay) => *base = overlay,
     }
+}
 
-    // セクションが末尾まで続いている
-    Some(yaml.len())
+/// トップレベルのリストをマージ
+/// mounts: location が同じエントリをマージし、それ以外は追加
+/// provision / probes: 末尾に追加
+/// portForwards: 先頭に追加（Lima は最初に一致したルールを使う）
+fn merge_list(key: &str, base: &mut Vec<Value>, overlay: Vec<Value>) {
+    match key {
+        "mounts" => {
+            for entry in overlay {
+                let existing = mount_location(&entry).and_then(|location| {
+                    base.iter_mut()
+                        .find(|e| mount_location(e) == Some(location))
+                });
+                match existing {
+                    Some(existing) => merge_value(existing, entry),
+                    None => base.push(entry),
+                }
+            }
+        }
+        "provision" | "probes" => base.extend(overlay),
+        "portForwards" => {
+            let rest = std::mem::take(base);
+            *base = overlay;
+            base.extend(rest);
+        }
+        _ => *base = overlay,
+    }
 }
 
-/// テンプレートを生成（カスタム or デフォルト）
-pub fn generate(config: &TemplateConfig) -> String {
-    if let Some(template_path) = &config.custom_template {
-        if let Ok(custom) = std::fs::read_to_string(template_path) {
-            // カスタムテンプレートに worktree_path を置換
-            let processed = custom
-                .replace("{{WORKTREE_PATH}}", &config.worktree_path)
-                .replace("{{USER}}", &config.user)
-                .replace("{{CPUS}}", &config.cpus.to_string())
-                .replace("{{MEMORY}}", &config.memory)
-                .replace("{{DISK}}", &config.disk);
-            return inject_provisions_into_template(&processed, &config.provision_scripts);
+/// 生成済みテンプレートにオーバーレイを適用
+fn apply_overlays(template: &str, overlays: &[Value]) -> Result<String> {
+    if overlays.is_empty() {
+        return Ok(template.to_string());
+    }
+
+    let mut root: Value =
+        serde_yaml::from_str(template).context("Failed to parse Lima template as YAML")?;
+    for overlay in overlays {
+        let (Value::Mapping(base), Value::Mapping(overlay)) = (&mut root, overlay) else {
+            anyhow::bail!("Lima template and overlays must be YAML mappings");
+        };
+        for (key, value) in overlay.clone() {
+            let list_key = key.as_str().unwrap_or_default().to_string();
+            match (base.get_mut(&key), value) {
+                (Some(Value::Sequence(existing)), Value::Sequence(entries)) => {
+                    merge_list(&list_key, existing, entries);
+                }
+                (_, Value::Null) => {
+                    base.remove(&key);
+                }
+                (Some(existing), value) => merge_value(existing, value),
+                (None, value) => {
+                    base.insert(key, value);
+                }
+            }
         }
     }
-    generate_default(config)
+
+    serde_yaml::to_string(&root).context("Failed to serialize Lima template")
+}
+
+/// テンプレートを生成（カスタム or デフォルト）し、オーバーレイを適用
+pub fn generate(config: &TemplateConfig) -> Result<String> {
+    let template = generate_base(config)?;
+    apply_overlays(&template, &config.overlays).context("Failed to apply Lima template overlay")
+}
+
+fn generate_base(config: &TemplateConfig) -> Result<String> {
+    let template_path = match &config.custom_template {
+        Some(path) => path,
+        None => return Ok(generate_default(config)),
+    };
+
+    let custom = std::fs::read_to_string(template_path)
+        .context(format!("Failed to read custom Lima template: {}", template_path))?;
+    let processed = placeholder::render(&custom, &config.placeholder_vars())
+        .context(format!("Invalid custom Lima template: {}", template_path))?;
+    inject_provisions_into_template(&processed, config)
+        .context(format!("Invalid custom Lima template: {}", template_path))
 }
 
 /// 一時テンプレートファイルを作成
 pub fn create_temp_template(config: &TemplateConfig) -> Result<tempfile::NamedTempFile> {
-    let content = generate(config);
+    config.prepare_mount_sources()?;
+    write_temp_template(&generate(config)?)
+}
 
+/// テンプレートの内容を一時ファイルに書き出す
+pub fn write_temp_template(content: &str) -> Result<tempfile::NamedTempFile> {
     let mut temp = tempfile::Builder::new()
         .prefix("fracta-lima-")
         .suffix(".yaml")
@@ -419,24 +1555,140 @@ mod tests {
     #[test]
     fn test_generate_template() {
         let config = TemplateConfig::new("/home/user/project", None, None);
-        let template = generate(&config);
+        let template = generate(&config).unwrap();
 
         assert!(template.contains("cpus: 4"));
         assert!(template.contains("memory: \"8GiB\""));
         assert!(template.contains("disk: \"50GiB\""));
         assert!(template.contains("user:\n  name: \"lima\""));
         assert!(template.contains("/home/user/project"));
-        assert!(template.contains("vmType: \"vz\""));
+        assert!(template.contains(&format!("vmType: \"{}\"", host_vm_type())));
         assert!(!template.contains("mountType"));
     }
 
+    #[test]
+    fn test_generate_platform_vz() {
+        let mut config = TemplateConfig::new("/home/user/project", None, None);
+        config.vm_type = "vz".to_string();
+        let template = generate_default(&config);
+
+        assert!(template.contains("vmType: \"vz\""));
+        assert!(template.contains("vzNAT: true"));
+        assert_eq!(template.contains("rosetta:"), host_arch() == "aarch64");
+    }
+
+    #[test]
+    fn test_generate_platform_qemu() {
+        let mut config = TemplateConfig::new("/home/user/project", None, None);
+        config.vm_type = "qemu".to_string();
+        config.arch = Some("x86_64".to_string());
+        let template = generate_default(&config);
+
+        assert!(template.contains("vmType: \"qemu\""));
+        assert!(template.contains("arch: \"x86_64\""));
+        assert!(!template.contains("rosetta"));
+        assert!(!template.contains("vzNAT"));
+    }
+
+    #[test]
+    fn test_generate_default_images() {
+        let config = TemplateConfig::new("/home/user/project", None, None);
+        let template = generate_default(&config);
+        assert!(template.contains("ubuntu-24.04-server-cloudimg-arm64.img"));
+        assert!(template.contains("ubuntu-24.04-server-cloudimg-amd64.img"));
+    }
+
+    #[test]
+    fn test_set_images_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("base.img"), b"image").unwrap();
+
+        let mut config = TemplateConfig::new("/home/user/project", None, None);
+        config
+            .set_images(
+                &[VmImage {
+                    location: "file://base.img".to_string(),
+                    arch: Some("arm64".to_string()),
+                    digest: Some(format!("sha256:{}", "AB".repeat(32))),
+                }],
+                dir.path(),
+            )
+            .unwrap();
+        let template = generate_default(&config);
+
+        let expected = dir.path().join("base.img");
+        assert!(template.contains(&format!("location: \"{}\"", expected.display())));
+        assert!(template.contains("arch: \"aarch64\""));
+        assert!(template.contains(&format!("digest: \"sha256:{}\"", "ab".repeat(32))));
+        assert!(!template.contains("cloud-images.ubuntu.com"));
+
+        let missing = VmImage {
+            location: "file:///nonexistent/base.img".to_string(),
+            arch: None,
+            digest: None,
+        };
+        assert!(config.set_images(&[missing], dir.path()).is_err());
+
+        let bad_digest = VmImage {
+            location: "file://base.img".to_string(),
+            arch: None,
+            digest: Some("sha256:abc".to_string()),
+        };
+        assert!(config.set_images(&[bad_digest], dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_generate_shared_network() {
+        let mut config = TemplateConfig::new("/home/user/project", None, None);
+        config.vm_type = "qemu".to_string();
+        config.network = "user-v2".to_string();
+        let template = generate_default(&config);
+        assert!(template.contains("networks:\n  - lima: user-v2"));
+
+        config.network = "socket_vmnet".to_string();
+        let template = generate_default(&config);
+        assert!(template.contains("  - lima: shared"));
+        assert!(!template.contains("vzNAT"));
+
+        assert!(normalize_network("bridged").is_err());
+        assert_eq!(shared_interface(&template).as_deref(), Some("lima0"));
+    }
+
+    #[test]
+    fn test_shared_interface_after_custom_networks() {
+        let mut config = TemplateConfig::new("/home/user/project", None, None);
+        config.network = "user-v2".to_string();
+        let custom = "networks:\n  - vzNAT: true\n";
+        let rendered = inject_provisions_into_template(custom, &config).unwrap();
+        assert_eq!(shared_interface(&rendered).as_deref(), Some("lima1"));
+
+        assert_eq!(shared_interface("networks:\n  - vzNAT: true\n"), None);
+        assert_eq!(shared_interface("vmType: qemu\n"), None);
+    }
+
+    #[test]
+    fn test_set_platform_validation() {
+        let mut config = TemplateConfig::default();
+        config.set_platform(Some("qemu"), Some("arm64")).unwrap();
+        assert_eq!(config.vm_type, "qemu");
+        assert_eq!(config.arch.as_deref(), Some("aarch64"));
+
+        assert!(config.set_platform(Some("hyperv"), None).is_err());
+        assert!(config.set_platform(None, Some("riscv")).is_err());
+
+        assert_eq!(resolve_platform("vz", true, "aarch64", "aarch64").unwrap(), "vz");
+        assert!(resolve_platform("vz", true, "x86_64", "aarch64").is_err());
+        assert_eq!(resolve_platform("vz", false, "x86_64", "aarch64").unwrap(), "qemu");
+        assert_eq!(resolve_platform("qemu", true, "x86_64", "aarch64").unwrap(), "qemu");
+    }
+
     #[test]
     fn test_generate_with_provision() {
         let mut config = TemplateConfig::new("/home/user/project", None, None);
-        config.provision_scripts = vec![
-            "#!/bin/bash\napt-get update\napt-get install -y curl".to_string(),
-        ];
-        let template = generate(&config);
+        config.provision_scripts = vec![ProvisionStep::new(
+            "#!/bin/bash\napt-get update\napt-get install -y curl",
+        )];
+        let template = generate(&config).unwrap();
 
         assert!(template.contains("Already provisioned"));
         assert!(template.contains("apt-get update"));
@@ -445,6 +1697,214 @@ mod tests {
         assert!(template.contains("timeout 600s"));
     }
 
+    #[test]
+    fn test_provision_script_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("base.sh"), "apt-get install -y jq\n").unwrap();
+        let config: Config = toml::from_str(
+            r#"
+vm_provision_scripts = [
+  "base.sh",
+  { script = "curl -fsSL https://sh.rustup.rs | sh -s -- -y", mode = "user", env = { RUSTUP_TOOLCHAIN = "1.80 'stable'" } },
+  { script = "sysctl -w vm.max_map_count=262144", mode = "boot", run = "always" },
+]
+"#,
+        )
+        .unwrap();
+
+        let mut template_config = TemplateConfig::new("/my/worktree", None, None);
+        template_config
+            .load_provision_scripts(config.vm_provision_scripts.as_ref().unwrap(), dir.path())
+            .unwrap();
+        let steps = &template_config.provision_scripts;
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].hash(), simple_hash("apt-get install -y jq\n"));
+        assert_eq!(steps[1].mode, "user");
+        assert!(steps[2].always);
+
+        let template = generate(&template_config).unwrap();
+        let parsed: Value = serde_yaml::from_str(&template).unwrap();
+        let provision = parsed["provision"].as_sequence().unwrap();
+        assert_eq!(provision.len(), 5);
+        assert!(provision[1]["script"].as_str().unwrap().contains("vm_network_check"));
+        assert_eq!(provision[3]["mode"], Value::String("user".to_string()));
+        let user_script = provision[3]["script"].as_str().unwrap();
+        assert!(user_script.contains("Wait for the network check"));
+        assert!(user_script.contains("export RUSTUP_TOOLCHAIN='1.80 '\\''stable'\\'''"));
+        assert!(user_script.contains("touch \"$HOME/.local/state/fracta/provisioned/"));
+
+        let boot_script = provision[4]["script"].as_str().unwrap();
+        assert_eq!(provision[4]["mode"], Value::String("boot".to_string()));
+        assert!(!boot_script.contains("Already provisioned"));
+        assert!(!boot_script.contains("Wait for the network check"));
+        assert!(boot_script.contains("cat /proc/sys/kernel/random/boot_id > \"/var/lib/fracta/provisioned/"));
+
+        let probe = parsed["probes"][1]["script"].as_str().unwrap();
+        assert_eq!(probe.matches("|| return 1").count(), 3);
+        assert!(probe.contains(&steps[2].ready_check()));
+
+        let invalid = [
+            r#"vm_provision_scripts = [{ script = "true", mode = "root" }]"#,
+            r#"vm_provision_scripts = [{ script = "true", run = "twice" }]"#,
+            r#"vm_provision_scripts = [{ path = "base.sh", script = "true" }]"#,
+            r#"vm_provision_scripts = [{ script = "true", env = { "BAD-NAME" = "x" } }]"#,
+        ];
+        for content in invalid {
+            let config: Config = toml::from_str(content).unwrap();
+            let mut template_config = TemplateConfig::new("/my/worktree", None, None);
+            assert!(template_config
+                .load_provision_scripts(config.vm_provision_scripts.as_ref().unwrap(), dir.path())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_network_check() {
+        let mut config = TemplateConfig::new("/my/worktree", None, None);
+        config.provision_scripts = vec![ProvisionStep::new("apt-get install -y jq")];
+
+        let check: Config =
+            toml::from_str("vm_network_check = { host = \"mirror.local:8080\", timeout = \"2m\" }")
+                .unwrap();
+        config.set_network_check(check.vm_network_check.as_ref().unwrap()).unwrap();
+        assert_eq!(config.network_check_timeout, 120);
+        let template = generate(&config).unwrap();
+        assert!(template.contains("getent hosts mirror.local > /dev/null && timeout 5 bash -c '</dev/tcp/mirror.local/8080'"));
+        assert!(template.contains("deadline=$((SECONDS + 120))"));
+        let parsed: Value = serde_yaml::from_str(&template).unwrap();
+        let hint = parsed["probes"][0]["hint"].as_str().unwrap();
+        assert!(hint.contains("cannot reach mirror.local:8080"));
+
+        // dependency は system の確認エントリより前に実行されるため、結果を待たずに直接確認する
+        let mut dependency = ProvisionStep::new("apt-get install -y jq");
+        dependency.mode = "dependency".to_string();
+        config.provision_scripts = vec![dependency.clone()];
+        let template = generate(&config).unwrap();
+        assert!(!template.contains(NETWORK_READY));
+        assert!(template.contains("until ( getent hosts mirror.local"));
+
+        config.provision_scripts.push(ProvisionStep::new("apt-get install -y curl"));
+        let parsed: Value = serde_yaml::from_str(&generate(&config).unwrap()).unwrap();
+        let scripts: Vec<(&str, &str)> = parsed["provision"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|p| (p["mode"].as_str().unwrap(), p["script"].as_str().unwrap()))
+            .collect();
+        let dependency_script = scripts
+            .iter()
+            .find(|(mode, _)| *mode == "dependency")
+            .unwrap()
+            .1;
+        assert!(!dependency_script.contains(NETWORK_READY));
+        assert!(scripts
+            .iter()
+            .any(|(mode, script)| *mode == "system" && script.contains(NETWORK_READY)));
+        config.provision_scripts = vec![ProvisionStep::new("apt-get install -y jq")];
+
+        let check: Config =
+            toml::from_str("vm_network_check = { command = \"curl -sf http://proxy:3128\" }").unwrap();
+        config.set_network_check(check.vm_network_check.as_ref().unwrap()).unwrap();
+        assert!(generate(&config).unwrap().contains("bash -c 'curl -sf http://proxy:3128'"));
+
+        config
+            .set_network_check(&NetworkCheckConfig::Simple("none".to_string()))
+            .unwrap();
+        let template = generate(&config).unwrap();
+        assert!(!template.contains("network-ready"));
+        let parsed: Value = serde_yaml::from_str(&template).unwrap();
+        assert_eq!(parsed["probes"].as_sequence().unwrap().len(), 1);
+
+        assert!(config
+            .set_network_check(&NetworkCheckConfig::Simple("bad host!".to_string()))
+            .is_err());
+        assert!(config
+            .set_network_check(&NetworkCheckConfig::Simple("host:port".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_user_probes() {
+        let config: Config = toml::from_str(
+            r#"
+vm_probes = [
+  { script = "docker info", hint = "Docker daemon is not answering", timeout = "2m" },
+  { script = "test -d ~/.npm" },
+]
+"#,
+        )
+        .unwrap();
+        let mut template_config = TemplateConfig::new("/my/worktree", None, None);
+        template_config.set_probes(config.vm_probes.as_ref().unwrap()).unwrap();
+        assert_eq!(template_config.probes[0].timeout, 120);
+        assert_eq!(template_config.probes[1].hint, "vm_probes[1] did not succeed");
+
+        // provision スクリプトがなくても probes が出力される
+        let parsed: Value = serde_yaml::from_str(&generate(&template_config).unwrap()).unwrap();
+        let probes = parsed["probes"].as_sequence().unwrap();
+        assert_eq!(probes.len(), 2);
+        assert_eq!(probes[0]["hint"], Value::String("Docker daemon is not answering".to_string()));
+        let script = probes[0]["script"].as_str().unwrap();
+        assert!(script.contains("  docker info\n"));
+        assert!(script.contains("deadline=$((SECONDS + 120))"));
+        assert!(script.contains("echo 'FRACTA_PROBE_FAILED: Docker daemon is not answering'"));
+
+        let custom = inject_provisions_into_template("probes:\n  - script: \"true\"\n", &template_config)
+            .unwrap();
+        let parsed: Value = serde_yaml::from_str(&custom).unwrap();
+        assert_eq!(parsed["probes"].as_sequence().unwrap().len(), 3);
+
+        let empty = [VmProbe {
+            script: " ".to_string(),
+            hint: None,
+            timeout: None,
+        }];
+        assert!(template_config.set_probes(&empty).is_err());
+    }
+
+    #[test]
+    fn test_docker_provisioning() {
+        let config: Config = toml::from_str(
+            r#"
+[vm_docker]
+version = "27.3.1"
+compose_version = "2.29.7"
+
+[vm_docker.daemon]
+log-driver = "local"
+registry-mirrors = ["https://mirror.example"]
+"#,
+        )
+        .unwrap();
+        let mut template_config = TemplateConfig::new("/my/worktree", None, None);
+        template_config.provision_scripts = vec![ProvisionStep::new("echo after docker")];
+        template_config.set_docker(config.vm_docker.as_ref().unwrap()).unwrap();
+        assert_eq!(template_config.provision_scripts.len(), 2);
+        assert_eq!(template_config.probes[0].script, "sudo docker info");
+
+        let script = &template_config.provision_scripts[0].content;
+        assert!(script.contains("DOCKER_VERSION=$(pick_version docker-ce '^([0-9]+:)?27\\.3\\.1-')"));
+        assert!(script.contains("docker-compose-plugin=\"$COMPOSE_VERSION\""));
+        assert!(script.contains("apt-mark hold docker-ce docker-ce-cli docker-compose-plugin"));
+        assert!(script.contains("\"log-driver\": \"local\""));
+
+        let template = generate(&template_config).unwrap();
+        let parsed: Value = serde_yaml::from_str(&template).unwrap();
+        let provision = parsed["provision"].as_sequence().unwrap();
+        // sudo 設定 → ネットワーク確認 → Docker → ユーザースクリプト
+        let docker_script = provision[2]["script"].as_str().unwrap();
+        assert!(docker_script.contains("Already provisioned"));
+        assert!(docker_script.contains("\nFRACTA_EOF\n"));
+        assert!(provision[3]["script"].as_str().unwrap().contains("echo after docker"));
+
+        let disabled: Config = toml::from_str("[vm_docker]\nenable = false\n").unwrap();
+        let mut template_config = TemplateConfig::new("/my/worktree", None, None);
+        template_config.set_docker(disabled.vm_docker.as_ref().unwrap()).unwrap();
+        assert!(template_config.provision_scripts.is_empty());
+
+        assert!(docker_version_pattern("27.3.1; rm -rf /").is_err());
+    }
+
     #[test]
     fn test_simple_hash_deterministic() {
         let h1 = simple_hash("hello");
@@ -473,19 +1933,226 @@ provision:
 networks:
   - vzNAT: true
 "#;
-        let scripts = vec!["echo 'hello'".to_string()];
-        let result = inject_provisions_into_template(template, &scripts);
+        let mut config = TemplateConfig::new("/my/worktree", None, None);
+        config.provision_scripts = vec![ProvisionStep::new("echo 'hello'")];
+        let result = inject_provisions_into_template(template, &config).unwrap();
 
         assert!(result.contains("custom setup"));
         assert!(result.contains("echo 'hello'"));
         assert!(result.contains("probes:"));
+
+        let parsed: Value = serde_yaml::from_str(&result).unwrap();
+        assert_eq!(parsed["provision"].as_sequence().unwrap().len(), 3);
+        assert_eq!(parsed["probes"].as_sequence().unwrap().len(), 2);
+        assert_eq!(parsed["networks"][0]["vzNAT"], Value::Bool(true));
+    }
+
+    #[test]
+    fn test_inject_into_flow_style_and_nested_keys() {
+        let template = r#"# comment before keys
+vmType: "qemu"
+mounts: [{location: "~"}]
+probes: []
+env:
+  provision: "not a section"
+provision: [{mode: system, script: "echo flow"}]
+"#;
+        let mut config = TemplateConfig::new("/my/worktree", None, None);
+        config.provision_scripts = vec![ProvisionStep::new("echo 'hello'")];
+        config.extra_mounts = vec![VmMount {
+            location: "/repo/data".to_string(),
+            mount_point: None,
+            writable: true,
+        }];
+        let result = inject_provisions_into_template(template, &config).unwrap();
+
+        let parsed: Value = serde_yaml::from_str(&result).unwrap();
+        assert_eq!(parsed["env"]["provision"], Value::String("not a section".to_string()));
+        assert_eq!(parsed["mounts"].as_sequence().unwrap().len(), 2);
+        assert_eq!(parsed["mounts"][1]["location"], Value::String("/repo/data".to_string()));
+        let provision = parsed["provision"].as_sequence().unwrap();
+        assert_eq!(provision.len(), 3);
+        assert_eq!(provision[0]["script"], Value::String("echo flow".to_string()));
+        assert_eq!(parsed["probes"].as_sequence().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_inject_rejects_malformed_template() {
+        let config = TemplateConfig::new("/my/worktree", None, None);
+        assert!(inject_provisions_into_template("mounts: [unclosed", &config).is_err());
+        assert!(inject_provisions_into_template("- just\n- a list\n", &config).is_err());
+
+        let mut config = config;
+        config.provision_scripts = vec![ProvisionStep::new("echo 'hello'")];
+        let err = inject_provisions_into_template("provision: \"oops\"\n", &config).unwrap_err();
+        assert!(err.to_string().contains("'provision' in custom Lima template must be a list"));
+    }
+
+    #[test]
+    fn test_extra_mounts_and_shared_caches() {
+        let mut config = TemplateConfig::new("/home/user/project", None, None);
+        config
+            .set_extra_mounts(
+                &[VmMount {
+                    location: "data".to_string(),
+                    mount_point: Some("/mnt/data".to_string()),
+                    writable: false,
+                }],
+                Path::new("/repo"),
+            )
+            .unwrap();
+        config.set_shared_caches(&["npm".to_string(), "cargo".to_string()]).unwrap();
+
+        let template = generate_default(&config);
+        assert!(template.contains("location: \"/repo/data\""));
+        assert!(template.contains("mountPoint: \"/mnt/data\""));
+        assert!(!template.contains("9p:"));
+        assert!(!template.contains("sshfs:"));
+
+        config.mount_type = "sshfs".to_string();
+        let template = generate_default(&config);
+        assert_eq!(template.matches("sshfs:\n      cache: true").count(), 4);
+        assert!(template.contains("mountPoint: \"{{.Home}}/.npm\""));
+        assert!(template.contains("mountPoint: \"{{.Home}}/.cargo/registry\""));
+
+        let custom = "vmType: \"qemu\"\nmountType: \"9p\"\nmounts:\n  - location: \"~\"\n";
+        let result = inject_provisions_into_template(custom, &config).unwrap();
+        let parsed: Value = serde_yaml::from_str(&result).unwrap();
+        let mounts = parsed["mounts"].as_sequence().unwrap();
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[1]["location"], Value::String("/repo/data".to_string()));
+        assert_eq!(mounts[1]["9p"]["cache"], Value::String("mmap".to_string()));
+        assert!(mounts[1].get("sshfs").is_none());
+
+        assert!(config.set_shared_caches(&["unknown".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_apply_overlays() {
+        let mut config = TemplateConfig::new("/my/worktree", None, None);
+        config.provision_scripts = vec![ProvisionStep::new("echo 'hello'")];
+        let overlay: Value = serde_yaml::from_str(
+            r#"
+mountType: "9p"
+cpus: 8
+containerd: null
+mounts:
+  - location: "/my/worktree"
+    writable: false
+  - location: "~/data"
+provision:
+  - mode: user
+    script: "echo overlay"
+probes:
+  - script: "true"
+portForwards:
+  - guestPort: 8080
+    hostPort: 18080
+"#,
+        )
+        .unwrap();
+        config.add_overlay(overlay).unwrap();
+        assert!(config.add_overlay(Value::Sequence(Vec::new())).is_err());
+
+        let result = generate(&config).unwrap();
+        let parsed: Value = serde_yaml::from_str(&result).unwrap();
+        assert_eq!(parsed["mountType"], Value::String("9p".to_string()));
+        assert_eq!(parsed["cpus"], Value::Number(8.into()));
+        assert!(parsed.get("containerd").is_none());
+        assert_eq!(parsed["user"]["name"], Value::String("lima".to_string()));
+
+        let mounts = parsed["mounts"].as_sequence().unwrap();
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0]["writable"], Value::Bool(false));
+        assert!(mounts[0]["sshfs"].is_null());
+        assert_eq!(mounts[1]["location"], Value::String("~/data".to_string()));
+
+        let provision = parsed["provision"].as_sequence().unwrap();
+        assert_eq!(provision.len(), 4);
+        assert_eq!(provision[3]["script"], Value::String("echo overlay".to_string()));
+        assert_eq!(parsed["probes"].as_sequence().unwrap().len(), 3);
+
+        let port_forwards = parsed["portForwards"].as_sequence().unwrap();
+        assert_eq!(port_forwards[0]["guestPort"], Value::Number(8080.into()));
+        assert_eq!(port_forwards[1]["ignore"], Value::Bool(true));
+    }
+
+    #[test]
+    fn test_load_overlays_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let fracta_dir = dir.path().join(".fracta");
+        std::fs::create_dir_all(&fracta_dir).unwrap();
+        std::fs::write(
+            fracta_dir.join("lima-template.overlay.yaml"),
+            "memory: \"12GiB\"\ncpus: 6\n",
+        )
+        .unwrap();
+
+        let config: Config = toml::from_str("[vm.template_overlay]\ncpus = 2\n").unwrap();
+        let mut template_config = TemplateConfig::new("/my/worktree", None, None);
+        template_config
+            .load_overlays(&config, dir.path(), dir.path())
+            .unwrap();
+        assert_eq!(template_config.overlays.len(), 2);
+
+        let parsed: Value = serde_yaml::from_str(&generate(&template_config).unwrap()).unwrap();
+        assert_eq!(parsed["memory"], Value::String("12GiB".to_string()));
+        assert_eq!(parsed["cpus"], Value::Number(2.into()));
     }
 
     #[test]
     fn test_custom_template_placeholders() {
         let mut config = TemplateConfig::new("/my/worktree", None, None);
         config.custom_template = None; // will use default
-        let template = generate(&config);
+        let template = generate(&config).unwrap();
         assert!(template.contains("/my/worktree"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("custom.yaml");
+        std::fs::write(
+            &path,
+            "mounts:\n  - location: \"{{WORKTREE_PATH}}\"\n    mountPoint: \"{{.Home}}/{{INSTANCE_NAME}}\"\nenv:\n  BRANCH: \"{{BRANCH}}\"\n  REPO: \"{{MAIN_REPO}}\"\n  MODE: \"{{MODE:-dev}}\"\n",
+        )
+        .unwrap();
+        config.main_repo = "/my/repo".to_string();
+        config.set_instance("feature-a", "feature/a");
+        config.custom_template = Some(path.to_string_lossy().to_string());
+
+        // 未知のプレースホルダはエラー
+        let err = generate(&config).unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown placeholder {{MODE}}"));
+
+        std::fs::write(
+            &path,
+            "mounts:\n  - location: \"{{WORKTREE_PATH}}\"\n    mountPoint: \"{{.Home}}/{{INSTANCE_NAME}}\"\nenv:\n  BRANCH: \"{{BRANCH}}\"\n  REPO: \"{{MAIN_REPO}}\"\n",
+        )
+        .unwrap();
+        let parsed: Value = serde_yaml::from_str(&generate(&config).unwrap()).unwrap();
+        assert_eq!(parsed["mounts"][0]["location"], Value::String("/my/worktree".to_string()));
+        assert_eq!(
+            parsed["mounts"][0]["mountPoint"],
+            Value::String("{{.Home}}/feature-a".to_string())
+        );
+        assert_eq!(parsed["env"]["BRANCH"], Value::String("feature/a".to_string()));
+        assert_eq!(parsed["env"]["REPO"], Value::String("/my/repo".to_string()));
+
+        // 読めないテンプレートはデフォルトにフォールバックせずエラー
+        config.custom_template = Some(dir.path().join("missing.yaml").to_string_lossy().to_string());
+        assert!(generate(&config).is_err());
+    }
+
+    #[test]
+    fn test_resolve_template_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = TemplateConfig::new("/my/worktree", None, None);
+        assert!(config
+            .resolve_template(Some("missing.yaml"), dir.path(), dir.path())
+            .is_err());
+
+        std::fs::write(dir.path().join("custom.yaml"), "vmType: qemu\n").unwrap();
+        config
+            .resolve_template(Some("custom.yaml"), dir.path(), dir.path())
+            .unwrap();
+        assert!(config.custom_template.unwrap().ends_with("custom.yaml"));
     }
 }
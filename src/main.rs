@@ -3,6 +3,7 @@ mod config;
 mod hooks;
 mod lima;
 mod images;
+mod preflight;
 mod state;
 mod utils;
 
@@ -25,12 +26,20 @@ enum VmCommands {
     Add {
         /// インスタンス名（省略時は worktree のディレクトリ名から自動生成）
         name: Option<String>,
+
+        /// ホスト資源（ディスク）のチェックで失敗しても続行
+        #[arg(long)]
+        force: bool,
     },
 
     /// Lima VM を起動（compose は起動しない）
     Start {
         /// worktree 名（省略時は現在ディレクトリの worktree）
         name: Option<String>,
+
+        /// ホスト資源（メモリ・ディスク）のチェックで失敗しても続行
+        #[arg(long)]
+        force: bool,
     },
 
     /// Lima VM を停止（compose は停止しない）
@@ -65,8 +74,176 @@ enum VmCommands {
     #[command(alias = "ls")]
     List,
 
-    /// デフォルトの Lima テンプレートを出力
-    Template,
+    /// 一定時間使われていない VM を停止（cron / daemon 向け）
+    IdleStop {
+        /// アイドル判定のしきい値（例: 2h, 30m。省略時は idle_stop_after）
+        #[arg(long)]
+        after: Option<String>,
+
+        /// 停止せず対象のみ表示
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// VM のリソース使用状況（CPU / メモリ / ディスク / コンテナ）を表示
+    Stats {
+        /// worktree 名（省略時は現在ディレクトリの worktree）
+        name: Option<String>,
+
+        /// 起動中の全 VM を表示
+        #[arg(long, conflicts_with = "name")]
+        all: bool,
+
+        /// JSON で出力
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// VM 内の Docker イメージ・ビルドキャッシュを削除
+    Prune {
+        /// worktree 名（省略時は現在ディレクトリの worktree）
+        name: Option<String>,
+
+        /// 全インスタンスの VM を対象にする
+        #[arg(long, conflicts_with = "name")]
+        all: bool,
+
+        /// 削除の強さ（dangling / unused / all、省略時は vm_prune_level）
+        #[arg(long)]
+        level: Option<String>,
+
+        /// 停止中の VM を一時的に起動して prune する
+        #[arg(long)]
+        start_stopped: bool,
+    },
+
+    /// VM の起動ログ（ha.stderr.log / serial log）を表示
+    Logs {
+        /// worktree 名（省略時は現在ディレクトリの worktree）
+        name: Option<String>,
+
+        /// ログを追従表示
+        #[arg(short, long)]
+        follow: bool,
+
+        /// VM 内の cloud-init / provision ログを表示
+        #[arg(long)]
+        provision: bool,
+
+        /// 表示する行数
+        #[arg(short = 'n', long, default_value_t = 100)]
+        lines: usize,
+    },
+
+    /// インスタンス用に解決した Lima テンプレートを出力
+    Template {
+        /// worktree 名（省略時は現在の worktree、worktree 外ではデフォルトテンプレート）
+        name: Option<String>,
+
+        /// limactl validate で検証
+        #[arg(long)]
+        validate: bool,
+
+        /// VM 作成時のテンプレート（~/.lima/<vm>/lima.yaml）との差分を表示
+        #[arg(long)]
+        diff: bool,
+    },
+
+    /// VM ベースイメージを管理（オフライン作成・固定イメージ用）
+    Image {
+        #[command(subcommand)]
+        command: VmImageCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum VmImageCommands {
+    /// URL からイメージをダウンロードして ~/.fracta/images に保存
+    Fetch {
+        /// イメージの URL
+        url: String,
+
+        /// 期待する digest（例: sha256:...）
+        #[arg(long)]
+        digest: Option<String>,
+
+        /// イメージの arch（aarch64|x86_64、省略時はファイル名から推測）
+        #[arg(long)]
+        arch: Option<String>,
+
+        /// 保存するファイル名（省略時は URL から決定）
+        #[arg(long)]
+        name: Option<String>,
+
+        /// 同名の保存済みイメージを上書きする
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// ローカルのイメージファイルを ~/.fracta/images に取り込む
+    Import {
+        /// イメージファイルのパス
+        path: String,
+
+        /// 期待する digest（例: sha256:...）
+        #[arg(long)]
+        digest: Option<String>,
+
+        /// イメージの arch（aarch64|x86_64、省略時はファイル名から推測）
+        #[arg(long)]
+        arch: Option<String>,
+
+        /// 保存するファイル名（省略時は元のファイル名）
+        #[arg(long)]
+        name: Option<String>,
+
+        /// 同名の保存済みイメージを上書きする
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// 保存済みイメージ一覧
+    #[command(alias = "ls")]
+    List,
+}
+
+#[derive(Subcommand)]
+enum ImagesCommands {
+    /// インスタンス VM 間で Docker イメージをコピー（ホストのキャッシュを経由しない）
+    Copy {
+        /// コピー元の worktree 名
+        from: String,
+
+        /// コピー先の worktree 名
+        to: String,
+
+        /// コピーするイメージ（省略時はコピー元の compose が参照するイメージ）
+        images: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// キャッシュ済みイメージの一覧（最終利用が新しい順）
+    #[command(alias = "list")]
+    Ls,
+
+    /// キャッシュの合計サイズ
+    Size,
+
+    /// 古いエントリと上限を超えた分を削除（最終利用が古い順）し、レジストリを GC
+    Prune {
+        /// 合計サイズの上限（例: 20GB、省略時は image_cache_max_size）
+        #[arg(long)]
+        max_size: Option<String>,
+
+        /// この期間使われていないエントリを削除（例: 3d、デフォルト: 7d）
+        #[arg(long)]
+        older_than: Option<String>,
+    },
+
+    /// キャッシュとレジストリのデータをすべて削除
+    Clear,
 }
 
 #[derive(Subcommand)]
@@ -139,6 +316,10 @@ enum Commands {
         /// worktree のみ作成（Lima VM を作成しない）
         #[arg(long)]
         worktree_only: bool,
+
+        /// ホスト資源（メモリ・ディスク）のチェックで失敗しても続行
+        #[arg(long)]
+        force: bool,
     },
 
     /// docker compose を起動（VM が停止中なら起動）
@@ -161,6 +342,10 @@ enum Commands {
         /// VM 内のコピー先ルートディレクトリ（例: /tmp/fracta-build）
         #[arg(long)]
         vm_build_dir: Option<String>,
+
+        /// ホスト資源（メモリ・ディスク）のチェックで失敗しても続行
+        #[arg(long)]
+        force: bool,
     },
 
     /// worktree を再起動
@@ -208,6 +393,13 @@ enum Commands {
     #[command(alias = "ls")]
     List,
 
+    /// VM 間ネットワーク（IP とホスト名）を表示
+    Net {
+        /// 起動中の全 VM の /etc/hosts にホスト名を書き込む
+        #[arg(long)]
+        sync_hosts: bool,
+    },
+
     /// Lima VM を直接操作
     Vm {
         #[command(subcommand)]
@@ -219,14 +411,26 @@ enum Commands {
         #[command(subcommand)]
         command: BrowserCommands,
     },
+
+    /// インスタンス VM の Docker イメージを操作
+    Images {
+        #[command(subcommand)]
+        command: ImagesCommands,
+    },
+
+    /// イメージ同期のキャッシュ（~/.fracta/cache）を管理
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
 
     let result = match cli.command {
-        Commands::Add { name, base_branch, worktree_only } => {
-            commands::add::execute(&name, base_branch, worktree_only)
+        Commands::Add { name, base_branch, worktree_only, force } => {
+            commands::add::execute(&name, base_branch, worktree_only, force)
         }
         Commands::Up {
             name,
@@ -234,6 +438,7 @@ fn main() {
             no_parallel_build,
             vm_build_copy,
             vm_build_dir,
+            force,
         } => {
             commands::up::execute(
                 name.as_deref(),
@@ -241,6 +446,7 @@ fn main() {
                 no_parallel_build,
                 vm_build_copy,
                 vm_build_dir.as_deref(),
+                force,
             )
         }
         Commands::Restart { name } => {
@@ -254,9 +460,10 @@ fn main() {
         }
         Commands::Status { name } => commands::status::execute(name.as_deref()),
         Commands::List => commands::vm::list(),
+        Commands::Net { sync_hosts } => commands::net::execute(sync_hosts),
         Commands::Vm { command } => match command {
-            VmCommands::Add { name } => commands::vm::add_vm(name.as_deref()),
-            VmCommands::Start { name } => commands::vm::start(name.as_deref()),
+            VmCommands::Add { name, force } => commands::vm::add_vm(name.as_deref(), force),
+            VmCommands::Start { name, force } => commands::vm::start(name.as_deref(), force),
             VmCommands::Stop { name } => commands::vm::stop(name.as_deref()),
             VmCommands::Shell { name, shell, workdir, tty, command } => commands::vm::shell(
                 name.as_deref(),
@@ -266,7 +473,55 @@ fn main() {
                 &command,
             ),
             VmCommands::List => commands::vm::list(),
-            VmCommands::Template => commands::vm::template(),
+            VmCommands::IdleStop { after, dry_run } => {
+                commands::idle::execute(after.as_deref(), dry_run)
+            }
+            VmCommands::Stats { name, all, json } => {
+                commands::stats::execute(name.as_deref(), all, json)
+            }
+            VmCommands::Prune { name, all, level, start_stopped } => {
+                commands::prune::execute(name.as_deref(), all, level.as_deref(), start_stopped)
+            }
+            VmCommands::Logs { name, follow, provision, lines } => {
+                commands::vm::logs(name.as_deref(), follow, provision, lines)
+            }
+            VmCommands::Template { name, validate, diff } => {
+                commands::vm::template(name.as_deref(), validate, diff)
+            }
+            VmCommands::Image { command } => match command {
+                VmImageCommands::Fetch { url, digest, arch, name, force } => {
+                    commands::vm_image::fetch(
+                        &url,
+                        digest.as_deref(),
+                        arch.as_deref(),
+                        name.as_deref(),
+                        force,
+                    )
+                }
+                VmImageCommands::Import { path, digest, arch, name, force } => {
+                    commands::vm_image::import(
+                        &path,
+                        digest.as_deref(),
+                        arch.as_deref(),
+                        name.as_deref(),
+                        force,
+                    )
+                }
+                VmImageCommands::List => commands::vm_image::list(),
+            },
+        },
+        Commands::Images { command } => match command {
+            ImagesCommands::Copy { from, to, images } => {
+                commands::images::copy(&from, &to, &images)
+            }
+        },
+        Commands::Cache { command } => match command {
+            CacheCommands::Ls => commands::cache::list(),
+            CacheCommands::Size => commands::cache::size(),
+            CacheCommands::Prune { max_size, older_than } => {
+                commands::cache::prune(max_size.as_deref(), older_than.as_deref())
+            }
+            CacheCommands::Clear => commands::cache::clear(),
         },
         Commands::Browser { command } => match command {
             BrowserCommands::Open { name, browser, url, proxy_port, head, no_head: _ } => {
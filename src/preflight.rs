@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+use crate::lima::client as lima;
+use crate::lima::template::{self, TemplateConfig};
+use crate::utils;
+
+/// VM が必要とするホスト資源
+#[derive(Debug, Clone, Copy)]
+pub struct VmResources {
+    /// メモリ（バイト、None ならメモリはチェックしない）
+    pub memory: Option<u64>,
+    /// 追加で必要なディスク（バイト）
+    pub disk: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HostMemory {
+    total: u64,
+    available: u64,
+}
+
+/// テンプレートで memory / disk を省略したときの Lima のデフォルト
+const LIMA_DEFAULT_MEMORY: &str = "4GiB";
+const LIMA_DEFAULT_DISK: &str = "100GiB";
+
+/// 新規作成する VM の資源（作成のみなら memory は None）
+///
+/// カスタムテンプレートやオーバーレイの指定を反映するため、生成後のテンプレートから読み取る。
+pub fn resources_for_new(config: &TemplateConfig, will_start: bool) -> Result<VmResources> {
+    let rendered = template::generate(config)?;
+    let yaml: serde_yaml::Value =
+        serde_yaml::from_str(&rendered).context("Failed to parse generated Lima template")?;
+    let size = |key: &str, default: &str| -> Result<u64> {
+        match yaml.get(key) {
+            Some(serde_yaml::Value::Number(n)) => n
+                .as_u64()
+                .context(format!("Invalid VM {} '{}'", key, n)),
+            Some(serde_yaml::Value::String(s)) => {
+                utils::parse_size(s).context(format!("Invalid VM {} '{}'", key, s))
+            }
+            Some(serde_yaml::Value::Null) | None => utils::parse_size(default),
+            Some(_) => anyhow::bail!("Invalid VM {} in Lima template", key),
+        }
+    };
+    Ok(VmResources {
+        memory: if will_start {
+            Some(size("memory", LIMA_DEFAULT_MEMORY)?)
+        } else {
+            None
+        },
+        disk: size("disk", LIMA_DEFAULT_DISK)?,
+    })
+}
+
+/// 既存の VM を起動するときの資源（ディスクは未確保分のみ）
+pub fn resources_for_existing(lima_instance: &str) -> Result<VmResources> {
+    let info = lima::list()?
+        .into_iter()
+        .find(|i| i.name == lima_instance)
+        .context(format!("Lima VM '{}' not found", lima_instance))?;
+    let used = dir_usage(&lima::instance_dir(lima_instance)).unwrap_or(0);
+    Ok(VmResources {
+        memory: Some(info.memory),
+        disk: info.disk.saturating_sub(used),
+    })
+}
+
+/// /proc/meminfo をパース（Linux）
+fn parse_meminfo(content: &str) -> Option<HostMemory> {
+    let field = |name: &str| -> Option<u64> {
+        let line = content.lines().find(|l| l.starts_with(name))?;
+        let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kb * 1024)
+    };
+    Some(HostMemory {
+        total: field("MemTotal:")?,
+        available: field("MemAvailable:")?,
+    })
+}
+
+/// vm_stat の出力から空きメモリ（free + inactive + speculative）を計算（macOS）
+fn parse_vm_stat(output: &str) -> Option<u64> {
+    let page_size: u64 = output
+        .lines()
+        .next()?
+        .split("page size of ")
+        .nth(1)?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    let pages = |name: &str| -> u64 {
+        output
+            .lines()
+            .find(|l| l.starts_with(name))
+            .and_then(|l| l.split(':').nth(1))
+            .and_then(|v| v.trim().trim_end_matches('.').parse().ok())
+            .unwrap_or(0)
+    };
+    Some((pages("Pages free") + pages("Pages inactive") + pages("Pages speculative")) * page_size)
+}
+
+fn host_memory() -> Result<HostMemory> {
+    if std::env::consts::OS == "macos" {
+        let total = Command::new("sysctl")
+            .args(["-n", "hw.memsize"])
+            .output()
+            .context("Failed to execute sysctl")?;
+        let total: u64 = String::from_utf8_lossy(&total.stdout)
+            .trim()
+            .parse()
+            .context("Failed to parse hw.memsize")?;
+        let vm_stat = Command::new("vm_stat")
+            .output()
+            .context("Failed to execute vm_stat")?;
+        let available = parse_vm_stat(&String::from_utf8_lossy(&vm_stat.stdout))
+            .context("Failed to parse vm_stat output")?;
+        return Ok(HostMemory { total, available });
+    }
+
+    let content =
+        std::fs::read_to_string("/proc/meminfo").context("Failed to read /proc/meminfo")?;
+    parse_meminfo(&content).context("Failed to parse /proc/meminfo")
+}
+
+/// df -Pk の出力から空き容量（バイト）を取り出す
+fn parse_df(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let kb: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// 既存の親ディレクトリまで遡ってから空き容量を取得
+fn free_disk(path: &Path) -> Result<u64> {
+    let mut dir = path.to_path_buf();
+    while !dir.exists() {
+        if !dir.pop() {
+            break;
+        }
+    }
+    let output = Command::new("df")
+        .arg("-Pk")
+        .arg(&dir)
+        .output()
+        .context("Failed to execute df")?;
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+        .context(format!("Failed to parse df output for {}", dir.display()))
+}
+
+/// ディレクトリの実使用量（du -sk）
+fn dir_usage(path: &Path) -> Option<u64> {
+    let output = Command::new("du").arg("-sk").arg(path).output().ok()?;
+    let kb: u64 = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// ホスト資源に対する問題点を列挙
+fn find_problems(
+    resources: VmResources,
+    memory: Option<HostMemory>,
+    running: &[lima::InstanceInfo],
+    free_disk: u64,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let (Some(needed), Some(host)) = (resources.memory, memory) {
+        let running_memory: u64 = running.iter().map(|i| i.memory).sum();
+        if needed > host.available {
+            problems.push(format!(
+                "Not enough free memory: the VM needs {}, but only {} is available",
+                utils::format_size(needed),
+                utils::format_size(host.available)
+            ));
+        }
+        if running_memory + needed > host.total {
+            problems.push(format!(
+                "Memory over-commit: {} running VM(s) use {} and this VM needs {}, but the host has {}",
+                running.len(),
+                utils::format_size(running_memory),
+                utils::format_size(needed),
+                utils::format_size(host.total)
+            ));
+        }
+    }
+
+    if resources.disk > free_disk {
+        problems.push(format!(
+            "Not enough disk space in ~/.lima: the VM may grow to {} more, but only {} is free",
+            utils::format_size(resources.disk),
+            utils::format_size(free_disk)
+        ));
+    }
+
+    problems
+}
+
+/// limactl create / start 前にホストの空きメモリ・ディスクを確認する
+pub fn check(lima_instance: &str, resources: VmResources, force: bool) -> Result<()> {
+    check_before_evicting(lima_instance, resources, force, &[])
+}
+
+/// evicting の VM を停止したあとの状態でホスト資源を確認する
+///
+/// 停止予定の VM は起動中の VM から除き、そのメモリは空きとして扱う。
+pub fn check_before_evicting(
+    lima_instance: &str,
+    resources: VmResources,
+    force: bool,
+    evicting: &[&str],
+) -> Result<()> {
+    let (evicted, running): (Vec<lima::InstanceInfo>, Vec<lima::InstanceInfo>) = lima::list()?
+        .into_iter()
+        .filter(|i| i.status == lima::InstanceStatus::Running && i.name != lima_instance)
+        .partition(|i| evicting.contains(&i.name.as_str()));
+    let memory = match resources.memory {
+        Some(_) => {
+            let mut host = host_memory()?;
+            let freed: u64 = evicted.iter().map(|i| i.memory).sum();
+            host.available = (host.available + freed).min(host.total);
+            Some(host)
+        }
+        None => None,
+    };
+    let free = free_disk(&lima::instance_dir(lima_instance))?;
+
+    let problems = find_problems(resources, memory, &running, free);
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    if force {
+        for problem in &problems {
+            eprintln!("Warning: {} (continuing because of --force)", problem);
+        }
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "Host resource check failed for '{}':\n  {}\nStop other VMs, reduce memory/disk, or retry with --force.",
+        lima_instance,
+        problems.join("\n  ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let content = "MemTotal:       16384000 kB\nMemFree:         1000000 kB\nMemAvailable:    8192000 kB\n";
+        let mem = parse_meminfo(content).unwrap();
+        assert_eq!(mem.total, 16384000 * 1024);
+        assert_eq!(mem.available, 8192000 * 1024);
+    }
+
+    #[test]
+    fn test_parse_vm_stat() {
+        let output = "Mach Virtual Memory Statistics: (page size of 16384 bytes)\nPages free:                               10.\nPages active:                             99.\nPages inactive:                           20.\nPages speculative:                         5.\n";
+        assert_eq!(parse_vm_stat(output), Some(35 * 16384));
+    }
+
+    #[test]
+    fn test_parse_df() {
+        let output = "Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/disk3s5 971350180 512000000 400000000 57% /System/Volumes/Data\n";
+        assert_eq!(parse_df(output), Some(400000000 * 1024));
+    }
+
+    #[test]
+    fn test_find_problems() {
+        let running = vec![lima::InstanceInfo {
+            name: "fracta-a".to_string(),
+            status: lima::InstanceStatus::Running,
+            memory: 8 * GIB,
+            disk: 50 * GIB,
+        }];
+        let host = HostMemory {
+            total: 16 * GIB,
+            available: 6 * GIB,
+        };
+        let resources = VmResources {
+            memory: Some(8 * GIB),
+            disk: 50 * GIB,
+        };
+
+        let problems = find_problems(resources, Some(host), &running, 100 * GIB);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Not enough free memory"));
+
+        let problems = find_problems(resources, Some(host), &[running[0].clone(), running[0].clone()], 10 * GIB);
+        assert_eq!(problems.len(), 3);
+
+        let create_only = VmResources { memory: None, disk: 10 * GIB };
+        assert!(find_problems(create_only, None, &running, 20 * GIB).is_empty());
+    }
+
+    #[test]
+    fn test_resources_for_new_reads_overlay() {
+        let mut config = TemplateConfig::new("/tmp/worktree", None, None);
+        let defaults = resources_for_new(&config, true).unwrap();
+        assert_eq!(defaults.memory, Some(utils::parse_size(&config.memory).unwrap()));
+        assert_eq!(defaults.disk, utils::parse_size(&config.disk).unwrap());
+
+        config
+            .overlays
+            .push(serde_yaml::from_str("memory: \"2GiB\"\ndisk: \"30GiB\"").unwrap());
+        let resources = resources_for_new(&config, true).unwrap();
+        assert_eq!(resources.memory, Some(2 * GIB));
+        assert_eq!(resources.disk, 30 * GIB);
+
+        assert_eq!(resources_for_new(&config, false).unwrap().memory, None);
+    }
+}
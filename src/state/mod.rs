@@ -43,6 +43,19 @@ pub struct Instance {
     pub active_proxy: Option<ProxyForward>,
     #[serde(default)]
     pub active_browser: Option<BrowserSession>,
+    /// 最後に fracta から操作した時刻（UNIX 秒）
+    #[serde(default)]
+    pub last_used_at: Option<u64>,
+    /// 最後に compose を操作した時刻（UNIX 秒）
+    #[serde(default)]
+    pub last_compose_at: Option<u64>,
+}
+
+impl Instance {
+    /// 最後の利用時刻（fracta 操作・compose 操作のうち新しい方）
+    pub fn last_activity(&self) -> Option<u64> {
+        self.last_used_at.max(self.last_compose_at)
+    }
 }
 
 /// v2: Lima 統合版の状態
@@ -159,21 +172,42 @@ impl StateV2 {
             Some(name) => self
                 .find_instance(name)
                 .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", name)),
-            None => {
-                let cwd = std::env::current_dir()
-                    .context("Failed to get current directory")?;
-                let instance = self.instances.iter().find(|inst| {
-                    utils::is_path_within(std::path::Path::new(&inst.path), &cwd)
-                });
-
-                match instance {
-                    Some(inst) => Ok(inst),
-                    None => anyhow::bail!("No instance found for current directory"),
-                }
-            }
+            None => match self.current_instance()? {
+                Some(inst) => Ok(inst),
+                None => anyhow::bail!("No instance found for current directory"),
+            },
         }
     }
 
+    /// カレントディレクトリを含むインスタンス（worktree 外なら None）
+    pub fn current_instance(&self) -> Result<Option<&Instance>> {
+        let cwd = std::env::current_dir()
+            .context("Failed to get current directory")?;
+        Ok(self.instances.iter().find(|inst| {
+            utils::is_path_within(std::path::Path::new(&inst.path), &cwd)
+        }))
+    }
+
+    /// fracta からの操作時刻を記録
+    pub fn touch_instance(&mut self, instance_name: &str) -> Result<()> {
+        let instance = self.find_instance_mut(instance_name)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", instance_name))?;
+
+        instance.last_used_at = Some(utils::unix_now());
+        Ok(())
+    }
+
+    /// compose の操作時刻を記録（fracta からの操作としても記録）
+    pub fn record_compose_activity(&mut self, instance_name: &str) -> Result<()> {
+        let instance = self.find_instance_mut(instance_name)
+            .ok_or_else(|| anyhow::anyhow!("Instance '{}' not found", instance_name))?;
+
+        let now = utils::unix_now();
+        instance.last_used_at = Some(now);
+        instance.last_compose_at = Some(now);
+        Ok(())
+    }
+
     /// SOCKS5 プロキシを追加
     pub fn add_proxy(&mut self, instance_name: &str, proxy: ProxyForward) -> Result<()> {
         self.port_allocations.insert(proxy.local_port, instance_name.to_string());
@@ -296,6 +330,8 @@ fn migrate_v1_to_v2(v1: StateV1) -> StateV2 {
                 active_forwards: Vec::new(),
                 active_proxy: None,
                 active_browser: None,
+                last_used_at: None,
+                last_compose_at: None,
             }
         })
         .collect();
@@ -338,6 +374,8 @@ mod tests {
             active_forwards: Vec::new(),
             active_proxy: None,
             active_browser: None,
+            last_used_at: None,
+            last_compose_at: None,
         };
 
         state.add_instance(instance);
@@ -360,6 +398,8 @@ mod tests {
                 active_forwards: Vec::new(),
                 active_proxy: None,
                 active_browser: None,
+                last_used_at: None,
+                last_compose_at: None,
             }],
             port_allocations: HashMap::new(),
         };
@@ -377,6 +417,37 @@ mod tests {
         assert!(state.instances[0].active_forwards.is_empty());
     }
 
+    #[test]
+    fn test_activity_timestamps() {
+        let mut state = StateV2 {
+            version: 2,
+            instances: vec![Instance {
+                name: "test".to_string(),
+                path: "/path/to/test".to_string(),
+                branch: "main".to_string(),
+                lima_instance: "fracta-test".to_string(),
+                active_forwards: Vec::new(),
+                active_proxy: None,
+                active_browser: None,
+                last_used_at: Some(100),
+                last_compose_at: Some(50),
+            }],
+            port_allocations: HashMap::new(),
+        };
+
+        assert_eq!(state.instances[0].last_activity(), Some(100));
+        state.record_compose_activity("test").unwrap();
+        let inst = &state.instances[0];
+        assert!(inst.last_compose_at.unwrap() > 100);
+        assert_eq!(inst.last_used_at, inst.last_compose_at);
+        assert!(state.touch_instance("missing").is_err());
+
+        // 旧 state.json（タイムスタンプなし）も読み込める
+        let json = r#"{"name":"a","path":"/a","branch":"a","lima_instance":"fracta-a"}"#;
+        let inst: Instance = serde_json::from_str(json).unwrap();
+        assert_eq!(inst.last_activity(), None);
+    }
+
     #[test]
     fn test_migrate_v1_to_v2() {
         let v1 = StateV1 {
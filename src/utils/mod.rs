@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::config::Config;
 
@@ -35,6 +36,53 @@ pub fn resolve_main_repo() -> Result<PathBuf> {
     Ok(main_repo)
 }
 
+/// ホスト側の fracta データディレクトリ（~/.fracta）
+pub fn fracta_home_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME").context("HOME not set")?;
+    Ok(PathBuf::from(home).join(".fracta"))
+}
+
+/// 先頭の `~/` をホームディレクトリに展開
+pub fn expand_home(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Ok(home) = std::env::var("HOME") {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// "sha256:<hex>" または "<hex>" を小文字の hex に正規化
+pub fn parse_digest(digest: &str) -> Result<String> {
+    let hex = digest.trim().strip_prefix("sha256:").unwrap_or(digest.trim());
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid digest '{}'. Expected sha256:<64 hex chars>", digest);
+    }
+    Ok(hex.to_lowercase())
+}
+
+/// ファイルの SHA-256 を計算（sha256sum / shasum を使用）
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let candidates: [(&str, &[&str]); 2] = [("sha256sum", &[]), ("shasum", &["-a", "256"])];
+    for (program, args) in candidates {
+        let output = match Command::new(program).args(args).arg(path).output() {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("{} failed for {}: {}", program, path.display(), stderr.trim());
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let hash = stdout.split_whitespace().next().unwrap_or_default();
+        if hash.len() == 64 {
+            return Ok(hash.to_lowercase());
+        }
+        anyhow::bail!("Unexpected {} output: {}", program, stdout.trim());
+    }
+    anyhow::bail!("Neither sha256sum nor shasum is available")
+}
+
 pub fn fracta_worktree_dir(worktree_path: &Path) -> PathBuf {
     worktree_path.join(".fracta")
 }
@@ -65,6 +113,109 @@ pub fn is_path_within(parent: &Path, child: &Path) -> bool {
     child.starts_with(&parent)
 }
 
+/// 現在時刻（UNIX 秒）
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// "90s", "30m", "2h", "1d", "1h30m", "20m0s" 形式の期間をパース
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    if input.is_empty() {
+        anyhow::bail!("Empty duration");
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => anyhow::bail!("Invalid duration '{}': unknown unit '{}'", input, c),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid duration '{}'", input))?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .with_context(|| format!("Invalid duration '{}': duration too large", input))?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        anyhow::bail!("Invalid duration '{}': missing unit (s/m/h/d)", input);
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+/// 期間を "1h 5m" 形式で表示
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes) = (secs / 86400, (secs % 86400) / 3600, (secs % 3600) / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// "8GiB", "512MiB", "1.5GB", "100kB", "42" 形式のサイズをバイト数にパース
+pub fn parse_size(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size '{}'", input))?;
+
+    let multiplier: f64 = match unit.trim() {
+        "" | "B" => 1.0,
+        "k" | "kB" | "KB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "T" | "TB" => 1e12,
+        "Ki" | "KiB" => 1024.0,
+        "Mi" | "MiB" => 1024.0 * 1024.0,
+        "Gi" | "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "Ti" | "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        other => anyhow::bail!("Invalid size '{}': unknown unit '{}'", input, other),
+    };
+
+    Ok((value * multiplier).round() as u64)
+}
+
+/// バイト数を "1.5 GiB" 形式で表示
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 /// ディレクトリ名やコンテナ名として使用できない文字をサニタイズ
 ///
 /// - `/` を `-` に置換
@@ -75,10 +226,104 @@ pub fn sanitize_name(name: &str) -> String {
     parts.join("-")
 }
 
+/// `*`（任意の文字列）と `?`（任意の 1 文字）だけの簡易 glob
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 直前の `*` の位置と、そこから照合を再開するテキストの位置
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("postgres:*", "postgres:16"));
+        assert!(glob_match("ghcr.io/org/*", "ghcr.io/org/app:1.0"));
+        assert!(glob_match("app-?eb", "app-web"));
+        assert!(glob_match("*worker*", "app-worker-1"));
+        assert!(!glob_match("postgres:*", "redis:7"));
+        assert!(!glob_match("app", "app-web"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "x"));
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let hex = "a".repeat(64);
+        assert_eq!(parse_digest(&format!("sha256:{}", hex)).unwrap(), hex);
+        assert_eq!(parse_digest(&hex.to_uppercase()).unwrap(), hex);
+        assert!(parse_digest("sha256:1234").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("20m0s").unwrap(), Duration::from_secs(1200));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("5w").is_err());
+        assert!(parse_duration("").is_err());
+        let err = parse_duration("213503982334602d").unwrap_err();
+        assert!(err.to_string().contains("duration too large"));
+        assert!(parse_duration(&format!("{}s1s", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("8GiB").unwrap(), 8 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("512MiB").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("1.5GB").unwrap(), 1_500_000_000);
+        assert_eq!(parse_size("100kB").unwrap(), 100_000);
+        assert_eq!(parse_size("42").unwrap(), 42);
+        assert!(parse_size("GiB").is_err());
+        assert!(parse_size("10XB").is_err());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536 * 1024 * 1024), "1.5 GiB");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h 5m");
+        assert_eq!(format_duration(Duration::from_secs(90000)), "1d 1h");
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(expand_home("~/.fracta"), PathBuf::from(home).join(".fracta"));
+        assert_eq!(expand_home("/abs/path"), PathBuf::from("/abs/path"));
+        assert_eq!(expand_home("rel/~/path"), PathBuf::from("rel/~/path"));
+    }
+
     #[test]
     fn test_sanitize_name() {
         assert_eq!(sanitize_name("develop3"), "develop3");